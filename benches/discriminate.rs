@@ -0,0 +1,100 @@
+//! Compares `Natural`, whose groups share one buffer of values, with the same
+//! counting sort giving each group a `Vec` of its own, and `Dense` with
+//! `Natural` over the same keys.  Run with `cargo bench --bench discriminate`.
+
+extern crate discrimination;
+
+use discrimination::discriminator::{Bounded, Dense};
+use discrimination::prelude::*;
+use std::time::{Duration, Instant};
+
+const LEN: usize = 5_000_000;
+const RUNS: usize = 5;
+
+// xorshift, so that every run discriminates the same keys
+fn random(len: usize, limit: usize) -> Vec<(usize, u64)> {
+    let mut state = 0x2545_f491_4f6c_dd1d_u64;
+    (0..len).map(|i| {
+                     state ^= state << 13;
+                     state ^= state >> 7;
+                     state ^= state << 17;
+                     (state as usize % limit, i as u64)
+                 })
+            .collect()
+}
+
+// the fastest of several runs, each on a fresh copy of `pairs`
+fn time<F: FnMut(Vec<(usize, u64)>) -> u64>(pairs: &[(usize, u64)], mut f: F) -> Duration {
+    (0..RUNS).map(|_| {
+                  let pairs = pairs.to_vec();
+                  let start = Instant::now();
+                  assert!(f(pairs) > 0);
+                  start.elapsed()
+              })
+             .min()
+             .unwrap_or_default()
+}
+
+// counts the keys, then moves each value into its own group's `Vec`
+fn owned(limit: usize, pairs: Vec<(usize, u64)>) -> Vec<Vec<u64>> {
+    let mut counts = vec![0; limit];
+    for kv in &pairs {
+        counts[kv.0] += 1;
+    }
+    let mut slots = vec![usize::MAX; limit];
+    let mut groups = Vec::new();
+    for (k, &count) in counts.iter().enumerate() {
+        if count > 0 {
+            slots[k] = groups.len();
+            groups.push(Vec::with_capacity(count));
+        }
+    }
+    for (k, v) in pairs {
+        groups[slots[k]].push(v);
+    }
+    groups
+}
+
+fn compare(limit: usize) {
+    let pairs = random(LEN, limit);
+    let desc = Natural::new(limit);
+    let shared = time(&pairs, |pairs| {
+        desc.discriminate_sorted(pairs).map(|g| g.fold(0, |a, v| a ^ v) | 1).sum()
+    });
+    let owned = time(&pairs, |pairs| {
+        owned(limit, pairs).into_iter()
+                           .map(|g| g.into_iter().fold(0, |a, v| a ^ v) | 1)
+                           .sum()
+    });
+    println!("{:>9} keys: shared buffer {:>8.1?}, a Vec per group {:>8.1?}",
+             limit,
+             shared,
+             owned);
+}
+
+// `Dense` buckets keys already known to fit without checking them again,
+// where `Natural` checks every one
+fn dense(len: usize) {
+    let pairs = random(len, 1 << 16);
+    let desc: Dense<{ 1 << 16 }> = Dense::new();
+    let dense = time(&pairs, |pairs| {
+        let pairs = pairs.into_iter().map(|(k, v)| (Bounded::from(k as u16), v));
+        desc.discriminate_sorted(pairs).map(|g| g.fold(0, |a, v| a ^ v) | 1).sum()
+    });
+    let natural = time(&pairs, |pairs| {
+        let pairs = pairs.into_iter().map(|(k, v)| (k as u16, v));
+        Natural::new(1usize << 16).discriminate_sorted(pairs)
+                                  .map(|g| g.fold(0, |a, v| a ^ v) | 1)
+                                  .sum()
+    });
+    println!("{:>9} pairs: dense {:>8.1?}, natural {:>8.1?}", len, dense, natural);
+}
+
+fn main() {
+    compare(1 << 8);
+    compare(1 << 16);
+    compare(1 << 20);
+    compare(LEN);
+    dense(1 << 14);
+    dense(LEN);
+}
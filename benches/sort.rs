@@ -0,0 +1,59 @@
+//! Compares `disc_sort_unstable_by_key` with `sort_unstable` on slices of
+//! random integers.  Run with `cargo bench --bench sort`.
+
+extern crate discrimination;
+
+use discrimination::prelude::*;
+use discrimination::sort::Radix;
+use std::time::{Duration, Instant};
+
+const LEN: usize = 5_000_000;
+const RUNS: usize = 5;
+
+// xorshift, so that every run sorts the same keys
+fn random(len: usize) -> Vec<u64> {
+    let mut state = 0x2545_f491_4f6c_dd1d_u64;
+    (0..len).map(|_| {
+                     state ^= state << 13;
+                     state ^= state >> 7;
+                     state ^= state << 17;
+                     state
+                 })
+            .collect()
+}
+
+// the fastest of several runs, each on a fresh copy of `keys`
+fn time<T: Clone, F: FnMut(&mut [T])>(keys: &[T], mut sort: F) -> Duration {
+    (0..RUNS).map(|_| {
+                  let mut keys = keys.to_vec();
+                  let start = Instant::now();
+                  sort(&mut keys);
+                  start.elapsed()
+              })
+             .min()
+             .unwrap_or_default()
+}
+
+fn compare<T: Clone + Ord, D: Radix<T>>(name: &str, keys: &[T], desc: &D) {
+    let mut sorted = keys.to_vec();
+    sorted.sort_unstable();
+    let mut disc = keys.to_vec();
+    disc.disc_sort_unstable_by_key(desc, |k| k.clone());
+    assert!(disc == sorted);
+
+    let disc = time(keys, |keys| keys.disc_sort_unstable_by_key(desc, |k| k.clone()));
+    let std = time(keys, |keys| keys.sort_unstable());
+    println!("{:>4}: disc_sort_unstable_by_key {:>8.1?}, sort_unstable {:>8.1?}",
+             name,
+             disc,
+             std);
+}
+
+fn main() {
+    let keys = random(LEN);
+    compare("u64", &keys, &U64);
+    let keys: Vec<u32> = keys.iter().map(|&k| k as u32).collect();
+    compare("u32", &keys, &U32);
+    let keys: Vec<i64> = random(LEN).into_iter().map(|k| k as i64).collect();
+    compare("i64", &keys, &I64);
+}
@@ -0,0 +1,362 @@
+//! Discrimination of pairs that arrive over time, such as in batches from a
+//! channel, rather than all at once from one iterator.
+
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+use discriminator::{Bounded, Dense, Discriminator, I128, I16, I32, I64, I8, Invert, Isize, Map,
+                    Natural, PendingBuckets, Product, Span, SpanKey, Sum, SumGroups, Trivial,
+                    U128, U16, U32, U64, U8, Usize, or_panic, single};
+use either::Either::{self, Left, Right};
+use error::DiscriminationError;
+
+/// Discriminators that can take their pairs as they arrive, and discriminate
+/// them once they all have.
+pub trait Incremental<'a, K: 'a>: Discriminator<'a, K> {
+    /// The pairs taken so far, in whatever form the discrimination needs.
+    type Pending<V: 'a>: 'a
+        where Self: 'a;
+
+    fn pending<V: 'a>(&'a self) -> Self::Pending<V>;
+
+    /// Takes one more pair, failing if its key can already be told not to fit.
+    fn push<V: 'a>(&'a self, pending: &mut Self::Pending<V>, key: K, value: V)
+        -> Result<(), DiscriminationError>;
+
+    fn finish<V: 'a>(&'a self, pending: Self::Pending<V>)
+        -> Result<Self::Groups<V>, DiscriminationError>;
+}
+
+/// Takes pairs over time with `push` and `extend`, then `finish`es into the
+/// same `Discriminator::Groups` that `discriminate_sorted` would give for all
+/// of them at once.
+///
+/// Bucketing discriminators such as `Natural` fill their buckets as pairs
+/// arrive, so nothing is held twice; the rest hold on to the pairs until the
+/// end.
+pub struct DiscriminatorBuilder<'a, K: 'a, V: 'a, D: ?Sized + Incremental<'a, K> + 'a> {
+    desc: &'a D,
+    pending: D::Pending<V>,
+    key: PhantomData<fn(K)>,
+}
+
+impl<'a, K: 'a, V: 'a, D: ?Sized + Incremental<'a, K>> DiscriminatorBuilder<'a, K, V, D> {
+    pub fn new(desc: &'a D) -> Self {
+        DiscriminatorBuilder { desc,
+                               pending: desc.pending(),
+                               key: PhantomData, }
+    }
+
+    pub fn push(&mut self, key: K, value: V) {
+        or_panic(self.try_push(key, value))
+    }
+
+    /// Like `push`, but an error leaves the pair out rather than panicking.
+    pub fn try_push(&mut self, key: K, value: V) -> Result<(), DiscriminationError> {
+        self.desc.push(&mut self.pending, key, value)
+    }
+
+    /// Pushes a whole batch, stopping at the first pair that can't be taken.
+    pub fn try_extend<I>(&mut self, batch: I) -> Result<(), DiscriminationError>
+        where I: IntoIterator<Item = (K, V)>
+    {
+        batch.into_iter().try_for_each(|(k, v)| self.try_push(k, v))
+    }
+
+    pub fn finish(self) -> D::Groups<V> {
+        or_panic(self.try_finish())
+    }
+
+    pub fn try_finish(self) -> Result<D::Groups<V>, DiscriminationError> {
+        self.desc.finish(self.pending)
+    }
+}
+
+impl<'a, K: 'a, V: 'a, D: ?Sized> Extend<(K, V)> for DiscriminatorBuilder<'a, K, V, D>
+    where D: Incremental<'a, K>
+{
+    fn extend<I>(&mut self, batch: I)
+        where I: IntoIterator<Item = (K, V)>
+    {
+        for (k, v) in batch {
+            self.push(k, v);
+        }
+    }
+}
+
+impl<'a, K: 'a, D: ?Sized> Incremental<'a, K> for &'a D where D: Incremental<'a, K> {
+    type Pending<V: 'a> = D::Pending<V>
+        where Self: 'a;
+
+    fn pending<V: 'a>(&'a self) -> D::Pending<V> {
+        (**self).pending()
+    }
+
+    fn push<V: 'a>(&'a self, pending: &mut D::Pending<V>, key: K, value: V)
+        -> Result<(), DiscriminationError>
+    {
+        (**self).push(pending, key, value)
+    }
+
+    fn finish<V: 'a>(&'a self, pending: D::Pending<V>)
+        -> Result<Self::Groups<V>, DiscriminationError>
+    {
+        (**self).finish(pending)
+    }
+}
+
+impl<'a, K: Into<usize> + Copy + 'a> Incremental<'a, K> for Natural {
+    type Pending<V: 'a> = PendingBuckets<V>;
+
+    fn pending<V: 'a>(&'a self) -> PendingBuckets<V> {
+        PendingBuckets::new()
+    }
+
+    fn push<V: 'a>(&'a self, pending: &mut PendingBuckets<V>, key: K, value: V)
+        -> Result<(), DiscriminationError>
+    {
+        self.push_pending(pending, key.into(), value)
+    }
+
+    fn finish<V: 'a>(&'a self, pending: PendingBuckets<V>)
+        -> Result<Self::Groups<V>, DiscriminationError>
+    {
+        Ok(self.finish_pending(pending))
+    }
+}
+
+impl<'a, K: Into<Bounded<N>> + 'a, const N: usize> Incremental<'a, K> for Dense<N> {
+    type Pending<V: 'a> = PendingBuckets<V>;
+
+    fn pending<V: 'a>(&'a self) -> PendingBuckets<V> {
+        PendingBuckets::new()
+    }
+
+    fn push<V: 'a>(&'a self, pending: &mut PendingBuckets<V>, key: K, value: V)
+        -> Result<(), DiscriminationError>
+    {
+        Dense::push_pending(pending, key.into(), value)
+    }
+
+    fn finish<V: 'a>(&'a self, pending: PendingBuckets<V>)
+        -> Result<Self::Groups<V>, DiscriminationError>
+    {
+        Ok(Natural::new(N).finish_pending(pending))
+    }
+}
+
+macro_rules! dense_incremental {
+    ($name:ident, $uint:ty) => {
+        impl<'a> Incremental<'a, $uint> for $name {
+            type Pending<V: 'a> = PendingBuckets<V>;
+
+            fn pending<V: 'a>(&'a self) -> PendingBuckets<V> {
+                PendingBuckets::new()
+            }
+
+            fn push<V: 'a>(&'a self, pending: &mut PendingBuckets<V>, key: $uint, value: V)
+                -> Result<(), DiscriminationError>
+            {
+                const DESC: &Dense<{ <$uint>::MAX as usize + 1 }> = &Dense;
+                DESC.push(pending, key, value)
+            }
+
+            fn finish<V: 'a>(&'a self, pending: PendingBuckets<V>)
+                -> Result<Self::Groups<V>, DiscriminationError>
+            {
+                const DESC: &Dense<{ <$uint>::MAX as usize + 1 }> = &Dense;
+                Incremental::<$uint>::finish(DESC, pending)
+            }
+        }
+    }
+}
+
+dense_incremental!(U8, u8);
+dense_incremental!(U16, u16);
+
+// discriminated only once every pair has arrived, since sorting by radix or
+// refining groups needs them all
+macro_rules! buffered_incremental {
+    ($name:ident, $key:ty) => {
+        impl<'a> Incremental<'a, $key> for $name {
+            type Pending<V: 'a> = Vec<($key, V)>;
+
+            fn pending<V: 'a>(&'a self) -> Vec<($key, V)> {
+                Vec::new()
+            }
+
+            fn push<V: 'a>(&'a self, pending: &mut Vec<($key, V)>, key: $key, value: V)
+                -> Result<(), DiscriminationError>
+            {
+                pending.push((key, value));
+                Ok(())
+            }
+
+            fn finish<V: 'a>(&'a self, pending: Vec<($key, V)>)
+                -> Result<Self::Groups<V>, DiscriminationError>
+            {
+                self.try_discriminate_sorted(pending)
+            }
+        }
+    }
+}
+
+buffered_incremental!(U32, u32);
+buffered_incremental!(U64, u64);
+buffered_incremental!(U128, u128);
+buffered_incremental!(Usize, usize);
+buffered_incremental!(I8, i8);
+buffered_incremental!(I16, i16);
+buffered_incremental!(I32, i32);
+buffered_incremental!(I64, i64);
+buffered_incremental!(I128, i128);
+buffered_incremental!(Isize, isize);
+
+impl<'a, K: SpanKey + 'a> Incremental<'a, K> for Span<K> {
+    type Pending<V: 'a> = Vec<(K, V)>;
+
+    fn pending<V: 'a>(&'a self) -> Vec<(K, V)> {
+        Vec::new()
+    }
+
+    fn push<V: 'a>(&'a self, pending: &mut Vec<(K, V)>, key: K, value: V)
+        -> Result<(), DiscriminationError>
+    {
+        self.check(key.to_i128())?;
+        pending.push((key, value));
+        Ok(())
+    }
+
+    fn finish<V: 'a>(&'a self, pending: Vec<(K, V)>)
+        -> Result<Self::Groups<V>, DiscriminationError>
+    {
+        self.try_discriminate_sorted(pending)
+    }
+}
+
+impl<'a, K: 'a> Incremental<'a, K> for Trivial {
+    type Pending<V: 'a> = Vec<V>;
+
+    fn pending<V: 'a>(&'a self) -> Vec<V> {
+        Vec::new()
+    }
+
+    fn push<V: 'a>(&'a self, pending: &mut Vec<V>, _: K, value: V)
+        -> Result<(), DiscriminationError>
+    {
+        pending.push(value);
+        Ok(())
+    }
+
+    fn finish<V: 'a>(&'a self, pending: Vec<V>) -> Result<Self::Groups<V>, DiscriminationError> {
+        Ok(single(pending))
+    }
+}
+
+impl<'a, K: 'a, D: ?Sized> Incremental<'a, K> for Invert<D>
+    where D: Incremental<'a, K>
+{
+    type Pending<V: 'a> = D::Pending<V>
+        where Self: 'a;
+
+    fn pending<V: 'a>(&'a self) -> D::Pending<V> {
+        self.0.pending()
+    }
+
+    fn push<V: 'a>(&'a self, pending: &mut D::Pending<V>, key: K, value: V)
+        -> Result<(), DiscriminationError>
+    {
+        self.0.push(pending, key, value)
+    }
+
+    fn finish<V: 'a>(&'a self, pending: D::Pending<V>)
+        -> Result<Self::Groups<V>, DiscriminationError>
+    {
+        self.0.finish(pending).map(Iterator::rev)
+    }
+}
+
+impl<'a, K: 'a, J: 'a, F, D: ?Sized> Incremental<'a, K> for Map<F, D>
+    where D: Incremental<'a, J>,
+          F: Fn(K) -> J
+{
+    type Pending<V: 'a> = D::Pending<V>
+        where Self: 'a;
+
+    fn pending<V: 'a>(&'a self) -> D::Pending<V> {
+        self.1.pending()
+    }
+
+    fn push<V: 'a>(&'a self, pending: &mut D::Pending<V>, key: K, value: V)
+        -> Result<(), DiscriminationError>
+    {
+        self.1.push(pending, (self.0)(key), value)
+    }
+
+    fn finish<V: 'a>(&'a self, pending: D::Pending<V>)
+        -> Result<Self::Groups<V>, DiscriminationError>
+    {
+        self.1.finish(pending)
+    }
+}
+
+/// Each branch takes its own pairs as they arrive.
+impl<'a, J: 'a, K: 'a, L, R: ?Sized> Incremental<'a, Either<J, K>> for Sum<L, R>
+    where L: Incremental<'a, J>,
+          R: Incremental<'a, K>
+{
+    type Pending<V: 'a> = (L::Pending<V>, R::Pending<V>)
+        where Self: 'a;
+
+    fn pending<V: 'a>(&'a self) -> Self::Pending<V> {
+        (self.left.pending(), self.right.pending())
+    }
+
+    fn push<V: 'a>(&'a self, pending: &mut Self::Pending<V>, key: Either<J, K>, value: V)
+        -> Result<(), DiscriminationError>
+    {
+        match key {
+            Left(j) => self.left.push(&mut pending.0, j, value),
+            Right(k) => self.right.push(&mut pending.1, k, value),
+        }
+    }
+
+    fn finish<V: 'a>(&'a self, pending: Self::Pending<V>)
+        -> Result<Self::Groups<V>, DiscriminationError>
+    {
+        let (left, right) = pending;
+        if self.is_right_biased {
+            let right = self.right.finish(right)?;
+            Ok(SumGroups::new(true, self.left.finish(left)?, right))
+        } else {
+            let left = self.left.finish(left)?;
+            Ok(SumGroups::new(false, left, self.right.finish(right)?))
+        }
+    }
+}
+
+/// The groups of the first component are only refined once every pair has
+/// arrived.
+impl<'a, J: 'a, K: 'a, L, R: ?Sized> Incremental<'a, (J, K)> for Product<L, R>
+    where L: Discriminator<'a, J>,
+          R: Discriminator<'a, K>
+{
+    type Pending<V: 'a> = Vec<((J, K), V)>
+        where Self: 'a;
+
+    fn pending<V: 'a>(&'a self) -> Vec<((J, K), V)> {
+        Vec::new()
+    }
+
+    fn push<V: 'a>(&'a self, pending: &mut Vec<((J, K), V)>, key: (J, K), value: V)
+        -> Result<(), DiscriminationError>
+    {
+        pending.push((key, value));
+        Ok(())
+    }
+
+    fn finish<V: 'a>(&'a self, pending: Vec<((J, K), V)>)
+        -> Result<Self::Groups<V>, DiscriminationError>
+    {
+        self.try_discriminate_sorted(pending)
+    }
+}
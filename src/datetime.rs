@@ -0,0 +1,446 @@
+use alloc::vec::Vec;
+use core::time::Duration;
+use discriminator::{Discriminator, I64, Map, Product, U32, U64};
+#[cfg(any(feature = "chrono", feature = "time"))]
+use discriminator::{I32, U8};
+#[cfg(feature = "std")]
+use discriminator::{Invert, Sum};
+#[cfg(feature = "std")]
+use either::Either::{self, Left, Right};
+#[cfg(feature = "std")]
+use std::time::{SystemTime, UNIX_EPOCH};
+
+type TimeSpanDesc = Map<fn(Duration) -> (u64, u32), Product<U64, U32>>;
+
+#[cfg(feature = "std")]
+type TimestampDesc = Map<fn(SystemTime) -> Either<Duration, Duration>,
+                         Sum<Invert<TimeSpan>, TimeSpan>>;
+
+/// Discriminates durations by whole seconds, then by nanoseconds.
+#[derive(Debug,Copy,Clone,Default)]
+pub struct TimeSpan;
+
+impl TimeSpan {
+    pub fn new() -> Self {
+        TimeSpan
+    }
+}
+
+impl<'a> Discriminator<'a, Duration> for TimeSpan {
+    type Groups<V: 'a> = <TimeSpanDesc as Discriminator<'a, Duration>>::Groups<V>;
+
+    fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> Self::Groups<V>
+        where I: IntoIterator,
+              I::Item: Into<(Duration, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        fn parts(d: Duration) -> (u64, u32) {
+            (d.as_secs(), d.subsec_nanos())
+        }
+        const DESC: &TimeSpanDesc = &Map(parts,
+                                         Product { is_right_biased: false,
+                                                   left: U64,
+                                                   right: U32, });
+        DESC.discriminate_sorted(pairs)
+    }
+}
+
+/// Discriminates system times chronologically, by their offset from the Unix
+/// epoch.  Times before the epoch come first, furthest from it first.
+#[cfg(feature = "std")]
+#[derive(Debug,Copy,Clone,Default)]
+pub struct Timestamp;
+
+#[cfg(feature = "std")]
+impl Timestamp {
+    pub fn new() -> Self {
+        Timestamp
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'a> Discriminator<'a, SystemTime> for Timestamp {
+    type Groups<V: 'a> = <TimestampDesc as Discriminator<'a, SystemTime>>::Groups<V>;
+
+    fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> Self::Groups<V>
+        where I: IntoIterator,
+              I::Item: Into<(SystemTime, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        fn since_epoch(t: SystemTime) -> Either<Duration, Duration> {
+            match t.duration_since(UNIX_EPOCH) {
+                Ok(after) => Right(after),
+                Err(before) => Left(before.duration()),
+            }
+        }
+        const DESC: &TimestampDesc = &Map(since_epoch,
+                                          Sum { is_right_biased: false,
+                                                left: Invert(TimeSpan),
+                                                right: TimeSpan, });
+        DESC.discriminate_sorted(pairs)
+    }
+}
+
+/// Discriminates calendar dates and date-times chronologically.
+///
+/// Dates are discriminated by year, then month, then day.  Date-times are
+/// discriminated by the instant they denote, with timezone-aware ones
+/// normalized to UTC and naive ones taken to already be in UTC.
+#[cfg(any(feature = "chrono", feature = "time"))]
+#[derive(Debug,Copy,Clone,Default)]
+pub struct Chronological;
+
+#[cfg(any(feature = "chrono", feature = "time"))]
+impl Chronological {
+    pub fn new() -> Self {
+        Chronological
+    }
+}
+
+#[cfg(any(feature = "chrono", feature = "time"))]
+type Ymd = ((i32, u8), u8);
+
+#[cfg(any(feature = "chrono", feature = "time"))]
+type YmdDesc = Product<Product<I32, U8>, U8>;
+
+#[cfg(any(feature = "chrono", feature = "time"))]
+const YMD: YmdDesc = Product { is_right_biased: false,
+                               left: Product { is_right_biased: false,
+                                               left: I32,
+                                               right: U8, },
+                               right: U8, };
+
+#[cfg(any(feature = "chrono", feature = "time"))]
+type Instant = (i64, u32);
+
+#[cfg(any(feature = "chrono", feature = "time"))]
+type InstantDesc = Product<I64, U32>;
+
+#[cfg(any(feature = "chrono", feature = "time"))]
+const INSTANT: InstantDesc = Product { is_right_biased: false,
+                                       left: I64,
+                                       right: U32, };
+
+/// A unit of the (proleptic Gregorian, UTC) calendar to group by.
+#[derive(Debug,Copy,Clone,PartialEq,Eq,Hash)]
+pub enum CalendarUnit {
+    Year,
+    Month,
+    /// Weeks starting on Monday, as in ISO 8601.
+    IsoWeek,
+    Day,
+    Hour,
+    Minute,
+    Second,
+}
+
+/// Dates and date-times that can be grouped by calendar unit.
+pub trait CalendarTime: Sized {
+    /// The index of the period of length `unit` that `self` falls in.  Later
+    /// periods have larger indices.
+    fn period(&self, unit: CalendarUnit) -> i64;
+
+    /// The start of the period of length `unit` that `self` falls in.
+    fn truncate(self, unit: CalendarUnit) -> Self;
+}
+
+// `days` counts days from any Monday
+#[cfg(any(feature = "chrono", feature = "time"))]
+fn period_of(unit: CalendarUnit,
+             (year, month): (i32, u32),
+             days: i64,
+             (hour, minute, second): (u32, u32, u32))
+             -> i64 {
+    let hours = days * 24 + hour as i64;
+    let minutes = hours * 60 + minute as i64;
+    match unit {
+        CalendarUnit::Year => year as i64,
+        CalendarUnit::Month => year as i64 * 12 + month as i64 - 1,
+        CalendarUnit::IsoWeek => days.div_euclid(7),
+        CalendarUnit::Day => days,
+        CalendarUnit::Hour => hours,
+        CalendarUnit::Minute => minutes,
+        CalendarUnit::Second => minutes * 60 + second as i64,
+    }
+}
+
+/// Discriminates dates and date-times by the calendar period they fall in.
+#[derive(Debug,Copy,Clone)]
+pub struct Calendar {
+    pub unit: CalendarUnit,
+}
+
+impl Calendar {
+    pub fn new(unit: CalendarUnit) -> Self {
+        Calendar { unit }
+    }
+
+    /// Groups `pairs` by calendar period, returning each group alongside the
+    /// start of its period.
+    pub fn groups<T, V, I>(&self, pairs: I) -> Vec<(T, Vec<V>)>
+        where T: CalendarTime,
+              I: IntoIterator<Item = (T, V)>,
+              I::IntoIter: DoubleEndedIterator
+    {
+        const DESC: &I64 = &I64;
+        DESC.discriminate_sorted(pairs.into_iter().map(|(k, v)| (k.period(self.unit), (k, v))))
+            .filter_map(|group| {
+                            let mut start = None;
+                            let values = group.map(|(k, v)| {
+                                                       if start.is_none() {
+                                                           start = Some(k.truncate(self.unit));
+                                                       }
+                                                       v
+                                                   })
+                                              .collect();
+                            start.map(|start| (start, values))
+                        })
+            .collect()
+    }
+}
+
+impl<'a, T: CalendarTime + 'a> Discriminator<'a, T> for Calendar {
+    type Groups<V: 'a> = <I64 as Discriminator<'a, i64>>::Groups<V>;
+
+    fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> Self::Groups<V>
+        where I: IntoIterator,
+              I::Item: Into<(T, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        const DESC: &I64 = &I64;
+        DESC.discriminate_sorted(pairs.into_iter().map(move |kv| {
+            let (k, v) = kv.into();
+            (k.period(self.unit), v)
+        }))
+    }
+}
+
+#[cfg(feature = "chrono")]
+mod with_chrono {
+    use chrono::{DateTime, Datelike, Days, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Timelike};
+    use discriminator::{Discriminator, Map};
+    use super::{CalendarTime, CalendarUnit, Chronological, INSTANT, Instant, InstantDesc, YMD, Ymd,
+                YmdDesc, period_of};
+
+    fn ymd(d: NaiveDate) -> Ymd {
+        ((d.year(), d.month() as u8), d.day() as u8)
+    }
+
+    fn instant(t: NaiveDateTime) -> Instant {
+        let t = t.and_utc();
+        (t.timestamp(), t.timestamp_subsec_nanos())
+    }
+
+    impl<'a> Discriminator<'a, NaiveDate> for Chronological {
+        type Groups<V: 'a> = <YmdDesc as Discriminator<'a, Ymd>>::Groups<V>;
+
+        fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> Self::Groups<V>
+            where I: IntoIterator,
+                  I::Item: Into<(NaiveDate, V)>,
+                  I::IntoIter: DoubleEndedIterator + 'a
+        {
+            const DESC: &Map<fn(NaiveDate) -> Ymd, YmdDesc> = &Map(ymd, YMD);
+            DESC.discriminate_sorted(pairs)
+        }
+    }
+
+    impl<'a> Discriminator<'a, NaiveDateTime> for Chronological {
+        type Groups<V: 'a> = <InstantDesc as Discriminator<'a, Instant>>::Groups<V>;
+
+        fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> Self::Groups<V>
+            where I: IntoIterator,
+                  I::Item: Into<(NaiveDateTime, V)>,
+                  I::IntoIter: DoubleEndedIterator + 'a
+        {
+            const DESC: &Map<fn(NaiveDateTime) -> Instant, InstantDesc> = &Map(instant, INSTANT);
+            DESC.discriminate_sorted(pairs)
+        }
+    }
+
+    impl<'a, Tz: TimeZone + 'a> Discriminator<'a, DateTime<Tz>> for Chronological {
+        type Groups<V: 'a> = <InstantDesc as Discriminator<'a, Instant>>::Groups<V>;
+
+        fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> Self::Groups<V>
+            where I: IntoIterator,
+                  I::Item: Into<(DateTime<Tz>, V)>,
+                  I::IntoIter: DoubleEndedIterator + 'a
+        {
+            const DESC: &InstantDesc = &INSTANT;
+            DESC.discriminate_sorted(pairs.into_iter().map(|kv| {
+                let (k, v) = kv.into();
+                ((k.timestamp(), k.timestamp_subsec_nanos()), v)
+            }))
+        }
+    }
+
+    impl CalendarTime for NaiveDate {
+        fn period(&self, unit: CalendarUnit) -> i64 {
+            // 0001-01-01 was a Monday
+            let days = self.num_days_from_ce() as i64 - 1;
+            period_of(unit, (self.year(), self.month()), days, (0, 0, 0))
+        }
+
+        fn truncate(self, unit: CalendarUnit) -> NaiveDate {
+            let start = match unit {
+                CalendarUnit::Year => NaiveDate::from_ymd_opt(self.year(), 1, 1),
+                CalendarUnit::Month => NaiveDate::from_ymd_opt(self.year(), self.month(), 1),
+                CalendarUnit::IsoWeek => {
+                    let into_week = self.weekday().num_days_from_monday();
+                    self.checked_sub_days(Days::new(into_week as u64))
+                }
+                _ => None,
+            };
+            start.unwrap_or(self)
+        }
+    }
+
+    impl CalendarTime for NaiveDateTime {
+        fn period(&self, unit: CalendarUnit) -> i64 {
+            let days = self.date().num_days_from_ce() as i64 - 1;
+            period_of(unit,
+                      (self.year(), self.month()),
+                      days,
+                      (self.hour(), self.minute(), self.second()))
+        }
+
+        fn truncate(self, unit: CalendarUnit) -> NaiveDateTime {
+            let time = match unit {
+                CalendarUnit::Hour => NaiveTime::from_hms_opt(self.hour(), 0, 0),
+                CalendarUnit::Minute => NaiveTime::from_hms_opt(self.hour(), self.minute(), 0),
+                CalendarUnit::Second => {
+                    NaiveTime::from_hms_opt(self.hour(), self.minute(), self.second())
+                }
+                _ => None,
+            };
+            self.date().truncate(unit).and_time(time.unwrap_or(NaiveTime::MIN))
+        }
+    }
+
+    impl<Tz: TimeZone> CalendarTime for DateTime<Tz> {
+        fn period(&self, unit: CalendarUnit) -> i64 {
+            self.naive_utc().period(unit)
+        }
+
+        fn truncate(self, unit: CalendarUnit) -> DateTime<Tz> {
+            self.timezone().from_utc_datetime(&self.naive_utc().truncate(unit))
+        }
+    }
+}
+
+#[cfg(feature = "time")]
+mod with_time {
+    use discriminator::{Discriminator, Map};
+    use super::{CalendarTime, CalendarUnit, Chronological, INSTANT, Instant, InstantDesc, YMD, Ymd,
+                YmdDesc, period_of};
+    use time::{Date, Month, OffsetDateTime, PrimitiveDateTime, Time, UtcOffset};
+
+    fn ymd(d: Date) -> Ymd {
+        ((d.year(), d.month() as u8), d.day())
+    }
+
+    fn primitive_instant(t: PrimitiveDateTime) -> Instant {
+        offset_instant(t.assume_utc())
+    }
+
+    fn offset_instant(t: OffsetDateTime) -> Instant {
+        (t.unix_timestamp(), t.nanosecond())
+    }
+
+    impl<'a> Discriminator<'a, Date> for Chronological {
+        type Groups<V: 'a> = <YmdDesc as Discriminator<'a, Ymd>>::Groups<V>;
+
+        fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> Self::Groups<V>
+            where I: IntoIterator,
+                  I::Item: Into<(Date, V)>,
+                  I::IntoIter: DoubleEndedIterator + 'a
+        {
+            const DESC: &Map<fn(Date) -> Ymd, YmdDesc> = &Map(ymd, YMD);
+            DESC.discriminate_sorted(pairs)
+        }
+    }
+
+    impl<'a> Discriminator<'a, PrimitiveDateTime> for Chronological {
+        type Groups<V: 'a> = <InstantDesc as Discriminator<'a, Instant>>::Groups<V>;
+
+        fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> Self::Groups<V>
+            where I: IntoIterator,
+                  I::Item: Into<(PrimitiveDateTime, V)>,
+                  I::IntoIter: DoubleEndedIterator + 'a
+        {
+            const DESC: &Map<fn(PrimitiveDateTime) -> Instant, InstantDesc> =
+                &Map(primitive_instant, INSTANT);
+            DESC.discriminate_sorted(pairs)
+        }
+    }
+
+    impl<'a> Discriminator<'a, OffsetDateTime> for Chronological {
+        type Groups<V: 'a> = <InstantDesc as Discriminator<'a, Instant>>::Groups<V>;
+
+        fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> Self::Groups<V>
+            where I: IntoIterator,
+                  I::Item: Into<(OffsetDateTime, V)>,
+                  I::IntoIter: DoubleEndedIterator + 'a
+        {
+            const DESC: &Map<fn(OffsetDateTime) -> Instant, InstantDesc> =
+                &Map(offset_instant, INSTANT);
+            DESC.discriminate_sorted(pairs)
+        }
+    }
+
+    impl CalendarTime for Date {
+        fn period(&self, unit: CalendarUnit) -> i64 {
+            // Julian day 0 was a Monday
+            let days = self.to_julian_day() as i64;
+            period_of(unit, (self.year(), self.month() as u32), days, (0, 0, 0))
+        }
+
+        fn truncate(self, unit: CalendarUnit) -> Date {
+            let start = match unit {
+                CalendarUnit::Year => Date::from_calendar_date(self.year(), Month::January, 1).ok(),
+                CalendarUnit::Month => Date::from_calendar_date(self.year(), self.month(), 1).ok(),
+                CalendarUnit::IsoWeek => {
+                    let days = self.to_julian_day();
+                    Date::from_julian_day(days - days.rem_euclid(7)).ok()
+                }
+                _ => None,
+            };
+            start.unwrap_or(self)
+        }
+    }
+
+    impl CalendarTime for PrimitiveDateTime {
+        fn period(&self, unit: CalendarUnit) -> i64 {
+            let days = self.date().to_julian_day() as i64;
+            period_of(unit,
+                      (self.year(), self.month() as u32),
+                      days,
+                      (self.hour() as u32, self.minute() as u32, self.second() as u32))
+        }
+
+        fn truncate(self, unit: CalendarUnit) -> PrimitiveDateTime {
+            let time = match unit {
+                CalendarUnit::Hour => Time::from_hms(self.hour(), 0, 0).ok(),
+                CalendarUnit::Minute => Time::from_hms(self.hour(), self.minute(), 0).ok(),
+                CalendarUnit::Second => {
+                    Time::from_hms(self.hour(), self.minute(), self.second()).ok()
+                }
+                _ => None,
+            };
+            PrimitiveDateTime::new(self.date().truncate(unit), time.unwrap_or(Time::MIDNIGHT))
+        }
+    }
+
+    impl CalendarTime for OffsetDateTime {
+        fn period(&self, unit: CalendarUnit) -> i64 {
+            let utc = self.to_offset(UtcOffset::UTC);
+            PrimitiveDateTime::new(utc.date(), utc.time()).period(unit)
+        }
+
+        fn truncate(self, unit: CalendarUnit) -> OffsetDateTime {
+            let utc = self.to_offset(UtcOffset::UTC);
+            PrimitiveDateTime::new(utc.date(), utc.time()).truncate(unit)
+                                                          .assume_utc()
+                                                          .to_offset(self.offset())
+        }
+    }
+}
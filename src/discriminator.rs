@@ -1,8 +1,30 @@
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::vec::{self, Vec};
+#[cfg(feature = "std")]
+use core::cell::RefCell;
+use core::cmp::Reverse;
+use core::convert::TryInto;
+use core::fmt;
+use core::num::{NonZeroI128, NonZeroI16, NonZeroI32, NonZeroI64, NonZeroI8, NonZeroIsize,
+                NonZeroU128, NonZeroU16, NonZeroU32, NonZeroU64, NonZeroU8, NonZeroUsize,
+                Wrapping};
+use core::iter::{self, FusedIterator, Rev};
+use core::marker::PhantomData;
+use core::mem::{self, MaybeUninit};
+use core::ops;
+use core::option;
+use core::slice;
 use either::Either::{self, Left, Right};
-use split_either::{SplitEitherLeft, SplitEitherRight, split_either};
-use std::vec;
+use error::DiscriminationError;
+use radix::{Bits, sort_pairs};
+use split_either::{NonAtomic, split_either};
 
 pub trait Discriminator<'a, K: 'a> {
+    /// The groups of values with equal keys, in key order.
+    type Groups<V: 'a>: DoubleEndedIterator<Item = Group<V>> + 'a
+        where Self: 'a;
+
     // fn discriminate<V, I>(&'a self, pairs: I)
     //     -> Discriminate<'a, K, V>
     //     where I: IntoIterator,
@@ -13,11 +35,52 @@ pub trait Discriminator<'a, K: 'a> {
     //     where I: IntoIterator,
     //           I::Item: Into<(K, V)>,
     //           I::IntoIter: DoubleEndedIterator + 'a;
-    fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> DiscriminateSorted<'a, K, V>
+    fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> Self::Groups<V>
         where I: IntoIterator,
               I::Item: Into<(K, V)>,
               I::IntoIter: DoubleEndedIterator + 'a;
 
+    /// Like `discriminate_sorted`, but reports keys that the discriminator
+    /// can't handle instead of panicking.
+    ///
+    /// Discriminators that can't fail needn't override this.  Those that
+    /// refine their groups lazily may still panic on keys that are only
+    /// reached during iteration.
+    fn try_discriminate_sorted<V: 'a, I>(&'a self, pairs: I)
+        -> Result<Self::Groups<V>, DiscriminationError>
+        where I: IntoIterator,
+              I::Item: Into<(K, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        Ok(self.discriminate_sorted(pairs))
+    }
+
+    /// Like `try_discriminate_sorted`, but takes any scratch space it needs
+    /// from `cx`, so that it can be reused by later discriminations.
+    ///
+    /// Discriminators that neither use scratch space nor hand it on to inner
+    /// discriminators needn't override this.
+    fn try_discriminate_sorted_in<V: 'a, I>(&'a self, cx: &mut DiscriminationContext, pairs: I)
+        -> Result<Self::Groups<V>, DiscriminationError>
+        where I: IntoIterator,
+              I::Item: Into<(K, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        let _ = cx;
+        self.try_discriminate_sorted(pairs)
+    }
+
+    /// Like `discriminate_sorted`, but takes any scratch space it needs from
+    /// `cx`.
+    fn discriminate_sorted_in<V: 'a, I>(&'a self, cx: &mut DiscriminationContext, pairs: I)
+        -> Self::Groups<V>
+        where I: IntoIterator,
+              I::Item: Into<(K, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        or_panic(self.try_discriminate_sorted_in(cx, pairs))
+    }
+
     fn by_ref(&'a self) -> &'a Self {
         self
     }
@@ -35,6 +98,13 @@ pub trait Discriminator<'a, K: 'a> {
         Map::new(f, self)
     }
 
+    fn try_map_key<J: 'a, E, F>(self, f: F) -> TryMap<F, Self>
+        where Self: Sized,
+              F: Fn(J) -> Result<K, E>
+    {
+        TryMap::new(f, self)
+    }
+
     fn sum_left<J: 'a, D>(self, other: D) -> Sum<Self, D>
         where Self: Sized,
               D: Discriminator<'a, J>
@@ -62,225 +132,477 @@ pub trait Discriminator<'a, K: 'a> {
     {
         Product::right_biased(self, other)
     }
+
+    fn prefix(self, depth: usize) -> Prefix<Self>
+        where Self: Sized
+    {
+        Prefix::new(self, depth)
+    }
+
+    fn lexicographic(self) -> Prefix<Self>
+        where Self: Sized
+    {
+        Prefix::unbounded(self)
+    }
 }
 
 impl<'a, K: 'a, D: ?Sized> Discriminator<'a, K> for &'a D where D: Discriminator<'a, K> {
-    fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> DiscriminateSorted<'a, K, V>
+    type Groups<V: 'a> = D::Groups<V>
+        where Self: 'a;
+
+    fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> Self::Groups<V>
         where I: IntoIterator,
               I::Item: Into<(K, V)>,
               I::IntoIter: DoubleEndedIterator + 'a {
         D::discriminate_sorted(&**self, pairs)
     }
+
+    fn try_discriminate_sorted<V: 'a, I>(&'a self, pairs: I)
+        -> Result<Self::Groups<V>, DiscriminationError>
+        where I: IntoIterator,
+              I::Item: Into<(K, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a {
+        D::try_discriminate_sorted(&**self, pairs)
+    }
+
+    fn try_discriminate_sorted_in<V: 'a, I>(&'a self, cx: &mut DiscriminationContext, pairs: I)
+        -> Result<Self::Groups<V>, DiscriminationError>
+        where I: IntoIterator,
+              I::Item: Into<(K, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a {
+        D::try_discriminate_sorted_in(&**self, cx, pairs)
+    }
+
+    fn discriminate_sorted_in<V: 'a, I>(&'a self, cx: &mut DiscriminationContext, pairs: I)
+        -> Self::Groups<V>
+        where I: IntoIterator,
+              I::Item: Into<(K, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a {
+        D::discriminate_sorted_in(&**self, cx, pairs)
+    }
 }
 
 impl<'a, K: 'a, D: ?Sized> Discriminator<'a, K> for &'a mut D where D: Discriminator<'a, K> {
-    fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> DiscriminateSorted<'a, K, V>
+    type Groups<V: 'a> = D::Groups<V>
+        where Self: 'a;
+
+    fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> Self::Groups<V>
         where I: IntoIterator,
               I::Item: Into<(K, V)>,
               I::IntoIter: DoubleEndedIterator + 'a {
         D::discriminate_sorted(&**self, pairs)
     }
+
+    fn try_discriminate_sorted<V: 'a, I>(&'a self, pairs: I)
+        -> Result<Self::Groups<V>, DiscriminationError>
+        where I: IntoIterator,
+              I::Item: Into<(K, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a {
+        D::try_discriminate_sorted(&**self, pairs)
+    }
+
+    fn try_discriminate_sorted_in<V: 'a, I>(&'a self, cx: &mut DiscriminationContext, pairs: I)
+        -> Result<Self::Groups<V>, DiscriminationError>
+        where I: IntoIterator,
+              I::Item: Into<(K, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a {
+        D::try_discriminate_sorted_in(&**self, cx, pairs)
+    }
+
+    fn discriminate_sorted_in<V: 'a, I>(&'a self, cx: &mut DiscriminationContext, pairs: I)
+        -> Self::Groups<V>
+        where I: IntoIterator,
+              I::Item: Into<(K, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a {
+        D::discriminate_sorted_in(&**self, cx, pairs)
+    }
 }
 
 impl<'a, K: 'a, D: ?Sized> Discriminator<'a, K> for Box<D> where D: Discriminator<'a, K> {
-    fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> DiscriminateSorted<'a, K, V>
+    type Groups<V: 'a> = D::Groups<V>
+        where Self: 'a;
+
+    fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> Self::Groups<V>
         where I: IntoIterator,
               I::Item: Into<(K, V)>,
               I::IntoIter: DoubleEndedIterator + 'a {
         D::discriminate_sorted(&**self, pairs)
     }
-}
 
-pub struct DiscriminateSorted<'a, K: 'a, V: 'a>(DiscriminateSortedImpl<'a, K, V>);
+    fn try_discriminate_sorted<V: 'a, I>(&'a self, pairs: I)
+        -> Result<Self::Groups<V>, DiscriminationError>
+        where I: IntoIterator,
+              I::Item: Into<(K, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a {
+        D::try_discriminate_sorted(&**self, pairs)
+    }
+
+    fn try_discriminate_sorted_in<V: 'a, I>(&'a self, cx: &mut DiscriminationContext, pairs: I)
+        -> Result<Self::Groups<V>, DiscriminationError>
+        where I: IntoIterator,
+              I::Item: Into<(K, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a {
+        D::try_discriminate_sorted_in(&**self, cx, pairs)
+    }
 
-enum DiscriminateSortedImpl<'a, K: 'a, V: 'a> {
-    One(Option<V>),
-    Trivial(Option<Box<DoubleEndedIterator<Item = (K, V)> + 'a>>),
-    Natural(vec::IntoIter<Vec<V>>),
-    Invert(Box<DiscriminateSorted<'a, K, V>>),
-    Opaque(Box<DoubleEndedIterator<Item = DiscriminateSortedGroup<'a, K, V>> + 'a>),
+    fn discriminate_sorted_in<V: 'a, I>(&'a self, cx: &mut DiscriminationContext, pairs: I)
+        -> Self::Groups<V>
+        where I: IntoIterator,
+              I::Item: Into<(K, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a {
+        D::discriminate_sorted_in(&**self, cx, pairs)
+    }
 }
 
-impl<'a, K, V> Iterator for DiscriminateSorted<'a, K, V> {
-    type Item = DiscriminateSortedGroup<'a, K, V>;
+/// The groups produced by bucketing discriminators such as `Natural`.
+pub type Bucketed<V> = vec::IntoIter<Group<V>>;
+
+/// A single group of values with equal keys.
+///
+/// A group either owns its values or takes them from a run of a buffer shared
+/// with the other groups of the same discrimination.
+pub struct Group<V>(Values<V>);
+
+enum Values<V> {
+    Owned(vec::IntoIter<V>),
+    Shared(Run<V>),
+}
 
-    fn next(&mut self) -> Option<DiscriminateSortedGroup<'a, K, V>> {
+impl<V> Group<V> {
+    /// The values that haven't been taken yet.
+    pub fn as_slice(&self) -> &[V] {
         match self.0 {
-            DiscriminateSortedImpl::One(ref mut v_opt) => {
-                v_opt.take()
-                     .map(|v| DiscriminateSortedGroup(DiscriminateSortedGroupImpl::One(Some(v))))
-            }
-            DiscriminateSortedImpl::Trivial(ref mut pairs_opt) => {
-                pairs_opt.take().map(|pairs| {
-                    DiscriminateSortedGroup(DiscriminateSortedGroupImpl::Trivial(pairs))
-                })
-            }
-            DiscriminateSortedImpl::Natural(ref mut inner) => {
-                loop {
-                    match inner.next() {
-                        Some(vs) => {
-                            if !vs.is_empty() {
-                                return Some(DiscriminateSortedGroup(
-                                DiscriminateSortedGroupImpl::Natural(vs.into_iter())));
-                            } else {
-                                continue;
-                            }
-                        }
-                        None => return None,
-                    }
-                }
-            }
-            DiscriminateSortedImpl::Invert(ref mut inner) => inner.next_back(),
-            DiscriminateSortedImpl::Opaque(ref mut inner) => inner.next(),
+            Values::Owned(ref values) => values.as_slice(),
+            Values::Shared(ref run) => run.as_slice(),
         }
     }
+}
 
-    fn size_hint(&self) -> (usize, Option<usize>) {
-        match self.0 {
-            DiscriminateSortedImpl::One(ref v_opt) => {
-                let n = v_opt.is_some() as usize;
-                (n, Some(n))
-            }
-            DiscriminateSortedImpl::Trivial(ref pairs_opt) => {
-                let n = pairs_opt.is_some() as usize;
-                (n, Some(n))
-            }
-            DiscriminateSortedImpl::Natural(ref inner) => (0, inner.size_hint().1),
-            DiscriminateSortedImpl::Invert(ref inner) => inner.size_hint(),
-            DiscriminateSortedImpl::Opaque(ref inner) => inner.size_hint(),
-        }
+impl<V> From<Vec<V>> for Group<V> {
+    fn from(values: Vec<V>) -> Self {
+        Group(Values::Owned(values.into_iter()))
     }
+}
 
-    fn fold<B, F>(self, init: B, mut f: F) -> B
-        where F: FnMut(B, DiscriminateSortedGroup<'a, K, V>) -> B
-    {
+impl<V: Clone> Clone for Group<V> {
+    fn clone(&self) -> Self {
+        Group::from(self.as_slice().to_vec())
+    }
+}
+
+impl<V: fmt::Debug> fmt::Debug for Group<V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Group").field(&self.as_slice()).finish()
+    }
+}
+
+impl<V> Iterator for Group<V> {
+    type Item = V;
+
+    fn next(&mut self) -> Option<V> {
         match self.0 {
-            DiscriminateSortedImpl::One(v_opt) => {
-                match v_opt {
-                    None => init,
-                    Some(v) => {
-                        f(init,
-                          DiscriminateSortedGroup(DiscriminateSortedGroupImpl::One(Some(v))))
-                    }
-                }
-            }
-            DiscriminateSortedImpl::Trivial(pairs_opt) => {
-                match pairs_opt {
-                    None => init,
-                    Some(pairs) => {
-                        f(init,
-                          DiscriminateSortedGroup(DiscriminateSortedGroupImpl::Trivial(pairs)))
-                    }
-                }
-            }
-            DiscriminateSortedImpl::Natural(inner) => {
-                inner.filter(|vs| !vs.is_empty())
-                     .map(|vs| {
-                         DiscriminateSortedGroup(
-                    DiscriminateSortedGroupImpl::Natural(vs.into_iter()))
-                     })
-                     .fold(init, f)
-            }
-            DiscriminateSortedImpl::Invert(inner) => inner.rev().fold(init, f),
-            DiscriminateSortedImpl::Opaque(inner) => inner.fold(init, f),
+            Values::Owned(ref mut values) => values.next(),
+            Values::Shared(ref mut run) => run.next(),
         }
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let n = self.as_slice().len();
+        (n, Some(n))
+    }
 }
 
-impl<'a, K, V> DoubleEndedIterator for DiscriminateSorted<'a, K, V> {
-    fn next_back(&mut self) -> Option<DiscriminateSortedGroup<'a, K, V>> {
+impl<V> DoubleEndedIterator for Group<V> {
+    fn next_back(&mut self) -> Option<V> {
         match self.0 {
-            DiscriminateSortedImpl::One(ref mut v_opt) => {
-                v_opt.take()
-                     .map(|v| DiscriminateSortedGroup(DiscriminateSortedGroupImpl::One(Some(v))))
-            }
-            DiscriminateSortedImpl::Trivial(ref mut pairs_opt) => {
-                pairs_opt.take().map(|pairs| {
-                    DiscriminateSortedGroup(DiscriminateSortedGroupImpl::Trivial(pairs))
-                })
-            }
-            DiscriminateSortedImpl::Natural(ref mut inner) => {
-                loop {
-                    match inner.next_back() {
-                        Some(vs) => {
-                            if !vs.is_empty() {
-                                return Some(DiscriminateSortedGroup(
-                                DiscriminateSortedGroupImpl::Natural(vs.into_iter())));
-                            } else {
-                                continue;
-                            }
-                        }
-                        None => return None,
-                    }
-                }
-            }
-            DiscriminateSortedImpl::Invert(ref mut inner) => inner.next(),
-            DiscriminateSortedImpl::Opaque(ref mut inner) => inner.next_back(),
+            Values::Owned(ref mut values) => values.next_back(),
+            Values::Shared(ref mut run) => run.next_back(),
         }
     }
 }
 
-pub struct DiscriminateSortedGroup<'a, K: 'a, V: 'a>(DiscriminateSortedGroupImpl<'a, K, V>);
+impl<V> ExactSizeIterator for Group<V> {}
+
+impl<V> FusedIterator for Group<V> {}
+
+// a buffer whose values are owned by the runs over it rather than by the
+// buffer itself, so dropping it only frees the memory
+struct Buffer<V>(Box<[MaybeUninit<V>]>);
+
+// the values in `start..end` of a buffer, which no other run overlaps
+struct Run<V> {
+    buffer: Arc<Buffer<V>>,
+    start: usize,
+    end: usize,
+}
+
+// SAFETY: a run only ever touches its own values, so sharing the buffer is no
+// different from owning them
+unsafe impl<V: Send> Send for Run<V> {}
 
-enum DiscriminateSortedGroupImpl<'a, K: 'a, V: 'a> {
-    One(Option<V>),
-    Trivial(Box<DoubleEndedIterator<Item = (K, V)> + 'a>),
-    Natural(vec::IntoIter<V>),
-    Opaque(Box<DoubleEndedIterator<Item = V> + 'a>),
+unsafe impl<V: Sync> Sync for Run<V> {}
+
+impl<V> Run<V> {
+    fn as_slice(&self) -> &[V] {
+        let values = &self.buffer.0[self.start..self.end];
+        // SAFETY: the values in a run that haven't been taken are initialized
+        unsafe { slice::from_raw_parts(values.as_ptr() as *const V, values.len()) }
+    }
+
+    // SAFETY: `i` must be in the run, and must be removed from it right after
+    unsafe fn read(&self, i: usize) -> V {
+        unsafe { self.buffer.0[i].as_ptr().read() }
+    }
 }
 
-impl<'a, K, V> Iterator for DiscriminateSortedGroup<'a, K, V> {
+impl<V> Iterator for Run<V> {
     type Item = V;
 
     fn next(&mut self) -> Option<V> {
-        match self.0 {
-            DiscriminateSortedGroupImpl::One(ref mut v_opt) => v_opt.take(),
-            DiscriminateSortedGroupImpl::Trivial(ref mut pairs) => pairs.next().map(|kv| kv.1),
-            DiscriminateSortedGroupImpl::Natural(ref mut inner) => inner.next(),
-            DiscriminateSortedGroupImpl::Opaque(ref mut inner) => inner.next(),
+        if self.start == self.end {
+            return None;
+        }
+        // SAFETY: `start` is in the run, and is removed from it straight away
+        let value = unsafe { self.read(self.start) };
+        self.start += 1;
+        Some(value)
+    }
+}
+
+impl<V> DoubleEndedIterator for Run<V> {
+    fn next_back(&mut self) -> Option<V> {
+        if self.start == self.end {
+            return None;
+        }
+        self.end -= 1;
+        // SAFETY: `end` was in the run until just now
+        Some(unsafe { self.read(self.end) })
+    }
+}
+
+impl<V> Drop for Run<V> {
+    fn drop(&mut self) {
+        self.for_each(drop);
+    }
+}
+
+// the values in `runs` must be initialized, and the runs mustn't overlap
+pub(crate) fn shared<V>(buffer: Box<[MaybeUninit<V>]>, runs: Vec<(usize, usize)>)
+                        -> Bucketed<V>
+{
+    let buffer = Arc::new(Buffer(buffer));
+    runs.into_iter()
+        .map(|(start, end)| {
+                 Group(Values::Shared(Run { buffer: buffer.clone(),
+                                            start,
+                                            end, }))
+             })
+        .collect::<Vec<_>>()
+        .into_iter()
+}
+
+// groups the pairs by key once they've been sorted by radix
+pub(crate) fn radix_sorted<B: Bits, V>(mut pairs: Vec<(B, V)>) -> Bucketed<V> {
+    sort_pairs(&mut pairs);
+    let mut runs = Vec::new();
+    let mut start = 0;
+    for i in 1..=pairs.len() {
+        if i == pairs.len() || pairs[i].0 != pairs[start].0 {
+            runs.push((start, i));
+            start = i;
+        }
+    }
+
+    grouped(pairs.into_iter().map(|kv| kv.1).collect(), runs)
+}
+
+// groups `values` by `runs`, which must cover them in order, in one buffer
+// shared by all of the groups; empty runs are dropped
+pub(crate) fn grouped<V>(values: Vec<V>, mut runs: Vec<(usize, usize)>) -> Bucketed<V> {
+    debug_assert!(runs.last().map_or(0, |run| run.1) == values.len());
+    runs.retain(|run| run.0 < run.1);
+    let values = values.into_boxed_slice();
+    // SAFETY: `MaybeUninit<V>` has the same layout as `V`
+    let buffer = unsafe { Box::from_raw(Box::into_raw(values) as *mut [MaybeUninit<V>]) };
+    shared(buffer, runs)
+}
+
+// all of `values` as one group, or no groups if there aren't any
+pub(crate) fn single<V>(values: Vec<V>) -> Bucketed<V> {
+    let group = if values.is_empty() { None } else { Some(Group::from(values)) };
+    group.into_iter().collect::<Vec<_>>().into_iter()
+}
+
+/// The groups of either `A` or `B`.
+// `Either` has an inherent `map` that would shadow `Iterator::map`
+#[derive(Debug,Clone)]
+pub enum EitherGroups<A, B> {
+    Left(A),
+    Right(B),
+}
+
+impl<A, B> Iterator for EitherGroups<A, B>
+    where A: Iterator,
+          B: Iterator<Item = A::Item>
+{
+    type Item = A::Item;
+
+    fn next(&mut self) -> Option<A::Item> {
+        match *self {
+            EitherGroups::Left(ref mut a) => a.next(),
+            EitherGroups::Right(ref mut b) => b.next(),
         }
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
-        match self.0 {
-            DiscriminateSortedGroupImpl::One(ref v_opt) => {
-                let n = v_opt.is_some() as usize;
-                (n, Some(n))
-            }
-            DiscriminateSortedGroupImpl::Trivial(ref pairs) => pairs.size_hint(),
-            DiscriminateSortedGroupImpl::Natural(ref inner) => inner.size_hint(),
-            DiscriminateSortedGroupImpl::Opaque(ref inner) => inner.size_hint(),
+        match *self {
+            EitherGroups::Left(ref a) => a.size_hint(),
+            EitherGroups::Right(ref b) => b.size_hint(),
         }
     }
+}
 
-    fn fold<B, F>(self, init: B, mut f: F) -> B
-        where F: FnMut(B, V) -> B
-    {
-        match self.0 {
-            DiscriminateSortedGroupImpl::One(v_opt) => {
-                match v_opt {
-                    None => init,
-                    Some(v) => f(init, v),
-                }
-            }
-            DiscriminateSortedGroupImpl::Trivial(pairs) => pairs.map(|kv| kv.1).fold(init, f),
-            DiscriminateSortedGroupImpl::Natural(inner) => inner.fold(init, f),
-            DiscriminateSortedGroupImpl::Opaque(inner) => inner.fold(init, f),
+impl<A, B> DoubleEndedIterator for EitherGroups<A, B>
+    where A: DoubleEndedIterator,
+          B: DoubleEndedIterator<Item = A::Item>
+{
+    fn next_back(&mut self) -> Option<A::Item> {
+        match *self {
+            EitherGroups::Left(ref mut a) => a.next_back(),
+            EitherGroups::Right(ref mut b) => b.next_back(),
         }
     }
 }
 
-impl<'a, K, V> DoubleEndedIterator for DiscriminateSortedGroup<'a, K, V> {
-    fn next_back(&mut self) -> Option<V> {
-        match self.0 {
-            DiscriminateSortedGroupImpl::One(ref mut v_opt) => v_opt.take(),
-            DiscriminateSortedGroupImpl::Trivial(ref mut pairs) => pairs.next_back().map(|kv| kv.1),
-            DiscriminateSortedGroupImpl::Natural(ref mut inner) => inner.next_back(),
-            DiscriminateSortedGroupImpl::Opaque(ref mut inner) => inner.next_back(),
+/// The groups of `A` followed by those of `B`, or the other way around.
+// unlike a `Chain` for each order, this names `A` and `B` only once
+#[derive(Debug,Clone)]
+pub struct SumGroups<A, B> {
+    is_right_biased: bool,
+    left: Option<A>,
+    right: Option<B>,
+}
+
+impl<A, B> SumGroups<A, B> {
+    pub(crate) fn new(is_right_biased: bool, left: A, right: B) -> Self {
+        SumGroups { is_right_biased,
+                    left: Some(left),
+                    right: Some(right), }
+    }
+}
+
+fn next_fused<I, F>(iter: &mut Option<I>, next: F) -> Option<I::Item>
+    where I: Iterator,
+          F: FnOnce(&mut I) -> Option<I::Item>
+{
+    let item = iter.as_mut().and_then(next);
+    if item.is_none() {
+        *iter = None;
+    }
+    item
+}
+
+impl<A, B> Iterator for SumGroups<A, B>
+    where A: Iterator,
+          B: Iterator<Item = A::Item>
+{
+    type Item = A::Item;
+
+    fn next(&mut self) -> Option<A::Item> {
+        if self.is_right_biased {
+            next_fused(&mut self.right, B::next).or_else(|| next_fused(&mut self.left, A::next))
+        } else {
+            next_fused(&mut self.left, A::next).or_else(|| next_fused(&mut self.right, B::next))
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (left_lo, left_hi) = self.left.as_ref().map_or((0, Some(0)), A::size_hint);
+        let (right_lo, right_hi) = self.right.as_ref().map_or((0, Some(0)), B::size_hint);
+        let hi = match (left_hi, right_hi) {
+            (Some(l), Some(r)) => l.checked_add(r),
+            _ => None,
+        };
+        (left_lo.saturating_add(right_lo), hi)
+    }
+}
+
+impl<A, B> DoubleEndedIterator for SumGroups<A, B>
+    where A: DoubleEndedIterator,
+          B: DoubleEndedIterator<Item = A::Item>
+{
+    fn next_back(&mut self) -> Option<A::Item> {
+        if self.is_right_biased {
+            next_fused(&mut self.left, A::next_back)
+                .or_else(|| next_fused(&mut self.right, B::next_back))
+        } else {
+            next_fused(&mut self.right, B::next_back)
+                .or_else(|| next_fused(&mut self.left, A::next_back))
         }
     }
 }
 
+// the helpers below stand in for closures when mapping the pairs passed to an
+// inner discriminator, since a closure's type names the input iterator's type
+// and nesting would double its length at every level
+struct Pairs<I, K, V> {
+    inner: I,
+    marker: PhantomData<fn() -> (K, V)>,
+}
+
+impl<I, K, V> Iterator for Pairs<I, K, V>
+    where I: Iterator,
+          I::Item: Into<(K, V)>
+{
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<(K, V)> {
+        self.inner.next().map(Into::into)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<I, K, V> DoubleEndedIterator for Pairs<I, K, V>
+    where I: DoubleEndedIterator,
+          I::Item: Into<(K, V)>
+{
+    fn next_back(&mut self) -> Option<(K, V)> {
+        self.inner.next_back().map(Into::into)
+    }
+}
+
+fn into_pairs<I, K, V>(pairs: I) -> Pairs<I::IntoIter, K, V>
+    where I: IntoIterator,
+          I::Item: Into<(K, V)>
+{
+    Pairs { inner: pairs.into_iter(),
+            marker: PhantomData, }
+}
+
+fn rekey<K, J, V, F>(f: &F) -> impl Fn((K, V)) -> (J, V) + '_
+    where F: Fn(K) -> J
+{
+    move |(k, v)| (f(k), v)
+}
+
+fn nest_left<J, K, V>(((j, k), v): ((J, K), V)) -> (J, (K, V)) {
+    (j, (k, v))
+}
+
+fn nest_right<J, K, V>(((j, k), v): ((J, K), V)) -> (K, (J, V)) {
+    (k, (j, v))
+}
+
+fn tag<J, K, V>((k, v): (Either<J, K>, V)) -> Either<(J, V), (K, V)> {
+    match k {
+        Left(j) => Left((j, v)),
+        Right(k) => Right((k, v)),
+    }
+}
+
 #[derive(Debug,Copy,Clone,Default)]
 pub struct Trivial;
 
@@ -291,27 +613,20 @@ impl Trivial {
 }
 
 impl<'a, K: 'a> Discriminator<'a, K> for Trivial {
-    fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> DiscriminateSorted<'a, K, V>
+    type Groups<V: 'a> = Bucketed<V>;
+
+    fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> Self::Groups<V>
         where I: IntoIterator,
               I::Item: Into<(K, V)>,
               I::IntoIter: DoubleEndedIterator + 'a
     {
-        let mut pairs = pairs.into_iter();
-
-        if pairs.size_hint().1.map_or(false, |n| n <= 1) {
-            return DiscriminateSorted(DiscriminateSortedImpl::One(pairs.next()
-                                                                       .map(|kv| kv.into().1)));
-        }
-
-        DiscriminateSorted(DiscriminateSortedImpl::Trivial(
-            Some(Box::new(pairs.map(|kv| kv.into())))))
+        single(pairs.into_iter().map(|kv| kv.into().1).collect())
     }
 }
 
 #[derive(Debug,Copy,Clone)]
 pub struct Natural {
     limit: usize,
-    is_unchecked: bool,
 }
 
 impl From<usize> for Natural {
@@ -344,113 +659,750 @@ impl Natural {
     {
         let limit = limit.into();
         debug_assert!(limit >= 2);
-        Natural { limit: limit,
-                  is_unchecked: false, }
-    }
-
-    pub unsafe fn new_unchecked<N>(limit: N) -> Natural
-        where N: Into<usize>
-    {
-        let limit = limit.into();
-        debug_assert!(limit >= 2);
-        Natural { limit: limit,
-                  is_unchecked: true, }
+        Natural { limit }
     }
 
     pub fn limit(&self) -> usize {
         self.limit
     }
 
-    fn bdisc<V, F, I>(&self, mut update: F, pairs: I) -> Vec<Vec<V>>
-        where F: FnMut(&mut Vec<V>, V),
+    // inputs at least half as long as the number of buckets are counted by
+    // key, shorter ones only by the keys that occur
+    fn bdisc<K, V, I>(&self, cx: &mut DiscriminationContext, pairs: I)
+        -> Result<Bucketed<V>, DiscriminationError>
+        where K: Into<usize>,
               I: DoubleEndedIterator,
-              I::Item: Into<(usize, V)>
+              I::Item: Into<(K, V)>
     {
-        // initialize buckets
-        let mut buckets = Vec::with_capacity(self.limit);
-        for _ in 0..self.limit {
-            buckets.push(Vec::new());
+        if pairs.size_hint().0 >= self.limit / 2 {
+            self.counted(cx, pairs)
+        } else {
+            self.sparse(cx, pairs)
         }
+    }
 
-        // fill buckets
+    fn counted<K, V, I>(&self, cx: &mut DiscriminationContext, pairs: I)
+        -> Result<Bucketed<V>, DiscriminationError>
+        where K: Into<usize>,
+              I: DoubleEndedIterator,
+              I::Item: Into<(K, V)>
+    {
+        let limit = self.limit;
+        let DiscriminationContext { ref mut keys, ref mut offsets, .. } = *cx;
+        keys.clear();
+        offsets.clear();
+        offsets.try_reserve(limit)?;
+        offsets.resize(limit, 0);
+
+        // count keys
+        let mut values = Vec::with_capacity(pairs.size_hint().0);
         for kv in pairs {
             let (k, v) = kv.into();
-            update(&mut buckets[k], v);
+            let k = k.into();
+            match offsets.get_mut(k) {
+                Some(count) => *count += 1,
+                None => return Err(DiscriminationError::OutOfRange { key: k, limit }),
+            }
+            keys.push(k);
+            values.push(v);
+        }
+
+        let runs = into_offsets(offsets);
+
+        // move every value to its place
+        let mut buffer = Box::new_uninit_slice(values.len());
+        for (&k, v) in keys.iter().zip(values) {
+            buffer[offsets[k]].write(v);
+            offsets[k] += 1;
         }
 
-        // return results
-        return buckets;
+        // every value has been moved in
+        Ok(shared(buffer, runs))
     }
 
-    unsafe fn bdisc_unchecked<V, F, I>(&self, mut update: F, pairs: I) -> Vec<Vec<V>>
-        where F: FnMut(&mut Vec<V>, V),
+    fn sparse<K, V, I>(&self, cx: &mut DiscriminationContext, pairs: I)
+        -> Result<Bucketed<V>, DiscriminationError>
+        where K: Into<usize>,
               I: DoubleEndedIterator,
-              I::Item: Into<(usize, V)>
+              I::Item: Into<(K, V)>
     {
-        // initialize buckets
-        let mut buckets = Vec::with_capacity(self.limit);
-        for _ in 0..self.limit {
-            buckets.push(Vec::new());
-        }
-
-        // fill buckets
+        self.prepare(cx)?;
+        let mut counts = Vec::new();
+        let mut values = Vec::with_capacity(pairs.size_hint().0);
         for kv in pairs {
             let (k, v) = kv.into();
-            update(buckets.get_unchecked_mut(k), v);
+            if let Err(e) = self.fill(cx, &mut counts, &mut values, k.into(), v) {
+                self.order(cx);
+                return Err(e);
+            }
         }
+        let order = self.order(cx);
+        Ok(scattered(counts, &order, values))
+    }
 
-        // return results
-        return buckets;
+    // the table maps every key to its bucket, so that only the keys that
+    // actually occur are counted
+    fn prepare(&self, cx: &mut DiscriminationContext) -> Result<(), DiscriminationError> {
+        let limit = self.limit;
+        cx.reset_slots();
+        let DiscriminationContext { ref mut slots, ref mut keys, .. } = *cx;
+        if slots.len() < limit {
+            slots.try_reserve(limit - slots.len())?;
+            slots.resize(limit, UNTOUCHED);
+        }
+        keys.clear();
+        Ok(())
     }
-}
 
-impl<'a> Discriminator<'a, usize> for Natural {
-    fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> DiscriminateSorted<'a, usize, V>
-        where I: IntoIterator,
-              I::Item: Into<(usize, V)>,
-              I::IntoIter: DoubleEndedIterator + 'a
+    // tags `v` with the bucket of `k`, starting one if `k` hasn't occurred yet
+    fn fill<V>(&self,
+               cx: &mut DiscriminationContext,
+               counts: &mut Vec<usize>,
+               values: &mut Vec<(usize, V)>,
+               k: usize,
+               v: V)
+               -> Result<(), DiscriminationError>
     {
-        let mut pairs = pairs.into_iter();
-
-        if pairs.size_hint().1.map_or(false, |n| n <= 1) {
-            return DiscriminateSorted(DiscriminateSortedImpl::One(pairs.next()
-                                                                       .map(|kv| kv.into().1)));
+        let limit = self.limit;
+        let slot = match cx.slots[..limit].get_mut(k) {
+            Some(slot) => slot,
+            None => return Err(DiscriminationError::OutOfRange { key: k, limit }),
+        };
+        if *slot == UNTOUCHED {
+            *slot = counts.len();
+            cx.keys.push(k);
+            counts.push(0);
         }
+        counts[*slot] += 1;
+        values.push((*slot, v));
+        Ok(())
+    }
 
-        let res = if self.is_unchecked {
-            unsafe { self.bdisc_unchecked(Vec::push, pairs) }
+    // returns the touched buckets in key order and clears their slots,
+    // sweeping the whole table only when that's cheaper than sorting their keys
+    fn order(&self, cx: &mut DiscriminationContext) -> Vec<usize> {
+        let limit = self.limit;
+        let DiscriminationContext { ref mut slots, ref mut keys, .. } = *cx;
+        let mut order = Vec::with_capacity(keys.len());
+        if keys.len() > 1 && limit / keys.len() <= keys.len().ilog2() as usize {
+            for slot in &mut slots[..limit] {
+                if *slot != UNTOUCHED {
+                    order.push(mem::replace(slot, UNTOUCHED));
+                }
+            }
         } else {
-            self.bdisc(Vec::push, pairs)
-        };
-        DiscriminateSorted(DiscriminateSortedImpl::Natural(res.into_iter()))
+            keys.sort_unstable();
+            for &k in keys.iter() {
+                order.push(mem::replace(&mut slots[k], UNTOUCHED));
+            }
+        }
+
+        cx.is_dirty = false;
+        order
     }
-}
 
-#[derive(Debug,Copy,Clone,Default)]
-pub struct U8;
+    // buckets are filled as pairs arrive, with a table of their own
+    pub(crate) fn push_pending<V>(&self, pending: &mut PendingBuckets<V>, k: usize, v: V)
+                                  -> Result<(), DiscriminationError>
+    {
+        if pending.cx.slots.len() < self.limit {
+            self.prepare(&mut pending.cx)?;
+        }
+        self.fill(&mut pending.cx, &mut pending.counts, &mut pending.values, k, v)
+    }
 
-impl U8 {
-    pub fn new() -> Self {
-        U8
+    pub(crate) fn finish_pending<V>(&self, mut pending: PendingBuckets<V>) -> Bucketed<V> {
+        let order = self.order(&mut pending.cx);
+        scattered(pending.counts, &order, pending.values)
     }
 }
 
-impl<'a> Discriminator<'a, u8> for U8 {
-    fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> DiscriminateSorted<'a, u8, V>
+// moves every value tagged with its bucket into that bucket's run of one
+// buffer, given how many each bucket has and the order they go in
+fn scattered<V>(mut counts: Vec<usize>, order: &[usize], values: Vec<(usize, V)>)
+                -> Bucketed<V>
+{
+    let mut runs = Vec::with_capacity(order.len());
+    let mut total = 0;
+    for &bucket in order {
+        let count = mem::replace(&mut counts[bucket], total);
+        runs.push((total, total + count));
+        total += count;
+    }
+
+    let mut buffer = Box::new_uninit_slice(values.len());
+    for (bucket, v) in values {
+        buffer[counts[bucket]].write(v);
+        counts[bucket] += 1;
+    }
+
+    // every value has been moved in
+    shared(buffer, runs)
+}
+
+// turns the counts into offsets, returning each non-empty bucket's run
+fn into_offsets(offsets: &mut [usize]) -> Vec<(usize, usize)> {
+    let mut runs = Vec::new();
+    let mut total = 0;
+    for offset in offsets.iter_mut() {
+        let count = mem::replace(offset, total);
+        if count > 0 {
+            runs.push((total, total + count));
+            total += count;
+        }
+    }
+    runs
+}
+
+/// The buckets that an incremental discrimination by `Natural` has filled so
+/// far.
+///
+/// Each value is only tagged with its bucket until the discrimination is
+/// finished, when they're all moved into one buffer that the groups share.
+#[derive(Debug)]
+pub struct PendingBuckets<V> {
+    cx: DiscriminationContext,
+    counts: Vec<usize>,
+    values: Vec<(usize, V)>,
+}
+
+impl<V> PendingBuckets<V> {
+    pub fn new() -> Self {
+        PendingBuckets { cx: DiscriminationContext::new(),
+                         counts: Vec::new(),
+                         values: Vec::new(), }
+    }
+}
+
+impl<V> Default for PendingBuckets<V> {
+    fn default() -> Self {
+        PendingBuckets::new()
+    }
+}
+
+/// Scratch space for discriminations, which can be passed to
+/// `discriminate_sorted_in` so that repeated discriminations reuse it rather
+/// than allocating their own.
+///
+/// Only the space indexed by key is kept, since the values can be of another
+/// type every time.  Its buckets are cleared as they're emptied, by revisiting
+/// just the ones that were touched.
+#[derive(Debug,Clone,Default)]
+pub struct DiscriminationContext {
+    // every key's bucket, or `UNTOUCHED` between discriminations
+    slots: Vec<usize>,
+    // whether a discrimination stopped part way through, leaving `slots` as
+    // it was
+    is_dirty: bool,
+    keys: Vec<usize>,
+    offsets: Vec<usize>,
+}
+
+impl DiscriminationContext {
+    pub const fn new() -> Self {
+        DiscriminationContext { slots: Vec::new(),
+                                is_dirty: false,
+                                keys: Vec::new(),
+                                offsets: Vec::new(), }
+    }
+
+    // marks the slots as in use, clearing them first if a discrimination that
+    // used them panicked
+    fn reset_slots(&mut self) {
+        if self.is_dirty {
+            for slot in &mut self.slots {
+                *slot = UNTOUCHED;
+            }
+        }
+        self.is_dirty = true;
+    }
+
+    #[cfg(feature = "std")]
+    fn shrink_to(&mut self, limit: usize) {
+        for table in &mut [&mut self.slots, &mut self.keys, &mut self.offsets] {
+            table.truncate(limit);
+            table.shrink_to(limit);
+        }
+    }
+}
+
+// marks the keys that haven't occurred in a discrimination yet
+const UNTOUCHED: usize = usize::MAX;
+
+// contexts for up to this many buckets are kept around between
+// discriminations that aren't given one
+#[cfg(feature = "std")]
+const SHARED_LIMIT: usize = u16::MAX as usize + 1;
+
+// and up to this many of them, enough for the groups of products nested a
+// couple of levels deep to each hold one
+#[cfg(feature = "std")]
+const SHARED_CONTEXTS: usize = 4;
+
+#[cfg(feature = "std")]
+thread_local! {
+    static CONTEXTS: RefCell<Vec<DiscriminationContext>> = const { RefCell::new(Vec::new()) };
+}
+
+// calls `f` with one of this thread's contexts
+fn with_context<R, F>(f: F) -> R
+    where F: FnOnce(&mut DiscriminationContext) -> R
+{
+    let mut cx = take_context();
+    let res = f(&mut cx);
+    give_back(cx);
+    res
+}
+
+// takes one of this thread's contexts, or a new one if they're all in use, for
+// as long as a discrimination needs it
+#[cfg(feature = "std")]
+fn take_context() -> DiscriminationContext {
+    CONTEXTS.with(|shared| shared.borrow_mut().pop()).unwrap_or_default()
+}
+
+#[cfg(feature = "std")]
+fn give_back(mut cx: DiscriminationContext) {
+    cx.shrink_to(SHARED_LIMIT);
+    // the thread may be exiting, if the context was held by groups that are
+    // being dropped along with its other locals
+    let _ = CONTEXTS.try_with(|shared| {
+                                  let mut shared = shared.borrow_mut();
+                                  if shared.len() < SHARED_CONTEXTS {
+                                      shared.push(cx);
+                                  }
+                              });
+}
+
+// without thread-locals to keep one in, every discrimination gets its own
+#[cfg(not(feature = "std"))]
+fn take_context() -> DiscriminationContext {
+    DiscriminationContext::new()
+}
+
+#[cfg(not(feature = "std"))]
+fn give_back(_: DiscriminationContext) {}
+
+pub(crate) fn or_panic<T>(res: Result<T, DiscriminationError>) -> T {
+    res.unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Keys are used directly as bucket indices, so any index type that converts
+/// losslessly to `usize` can be discriminated without an intermediate `Map`.
+impl<'a, K: Into<usize> + Copy + 'a> Discriminator<'a, K> for Natural {
+    type Groups<V: 'a> = Bucketed<V>;
+
+    fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> Self::Groups<V>
         where I: IntoIterator,
-              I::Item: Into<(u8, V)>,
+              I::Item: Into<(K, V)>,
               I::IntoIter: DoubleEndedIterator + 'a
     {
-        // when `const fn` support goes stable, this will be nicer
-        fn conv(k: u8) -> usize {
-            k as usize
+        or_panic(self.try_discriminate_sorted(pairs))
+    }
+
+    fn try_discriminate_sorted<V: 'a, I>(&'a self, pairs: I)
+        -> Result<Self::Groups<V>, DiscriminationError>
+        where I: IntoIterator,
+              I::Item: Into<(K, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        with_context(|cx| self.try_discriminate_sorted_in(cx, pairs))
+    }
+
+    fn try_discriminate_sorted_in<V: 'a, I>(&'a self, cx: &mut DiscriminationContext, pairs: I)
+        -> Result<Self::Groups<V>, DiscriminationError>
+        where I: IntoIterator,
+              I::Item: Into<(K, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        let pairs = pairs.into_iter();
+
+        if pairs.size_hint().1.is_some_and(|n| n <= 1) {
+            let limit = self.limit;
+            let values = pairs.map(|kv| {
+                                   let (k, v) = kv.into();
+                                   match k.into() {
+                                       k if k < limit => Ok(v),
+                                       key => Err(DiscriminationError::OutOfRange { key, limit }),
+                                   }
+                               })
+                              .collect::<Result<_, _>>()?;
+            return Ok(single(values));
+        }
+
+        self.bdisc(cx, pairs)
+    }
+}
+
+/// An index that is known to be less than `N`.
+#[derive(Debug,Copy,Clone,PartialEq,Eq,PartialOrd,Ord,Hash)]
+pub struct Bounded<const N: usize>(usize);
+
+impl<const N: usize> Bounded<N> {
+    pub fn new(k: usize) -> Option<Self> {
+        if k < N {
+            Some(Bounded(k))
+        } else {
+            None
+        }
+    }
+
+    pub fn get(self) -> usize {
+        self.0
+    }
+
+    // the one place that relies on a `Bounded` being below `N`
+    fn index<T>(self, table: &mut [T; N]) -> &mut T {
+        // SAFETY: the field is private to this module, where every `Bounded`
+        // is checked against `N` or made from a type with fewer values
+        unsafe { table.get_unchecked_mut(self.0) }
+    }
+}
+
+// the first `N` entries of `table`, which must have at least that many
+fn first<const N: usize>(table: &mut [usize]) -> &mut [usize; N] {
+    (&mut table[..N]).try_into().expect("tables are at least `N` long")
+}
+
+impl<const N: usize> From<Bounded<N>> for usize {
+    fn from(k: Bounded<N>) -> usize {
+        k.0
+    }
+}
+
+impl From<bool> for Bounded<2> {
+    fn from(k: bool) -> Self {
+        Bounded(k as usize)
+    }
+}
+
+impl From<u8> for Bounded<{ u8::MAX as usize + 1 }> {
+    fn from(k: u8) -> Self {
+        Bounded(k as usize)
+    }
+}
+
+impl From<u16> for Bounded<{ u16::MAX as usize + 1 }> {
+    fn from(k: u16) -> Self {
+        Bounded(k as usize)
+    }
+}
+
+/// Discriminates keys that convert to `Bounded<N>` into `N` buckets.
+///
+/// Since every such key has already been checked against `N`, the buckets are
+/// indexed without checking them again, and discriminating them can only fail
+/// if the buckets can't be allocated.
+#[derive(Debug,Copy,Clone,Default)]
+pub struct Dense<const N: usize>;
+
+impl<const N: usize> Dense<N> {
+    pub fn new() -> Self {
+        Dense
+    }
+
+    // as `Natural::counted`, but with keys that need no checks
+    fn counted<K, V, I>(cx: &mut DiscriminationContext, pairs: I)
+        -> Result<Bucketed<V>, DiscriminationError>
+        where K: Into<Bounded<N>>,
+              I: Iterator,
+              I::Item: Into<(K, V)>
+    {
+        let offsets = &mut cx.offsets;
+        offsets.clear();
+        offsets.try_reserve(N)?;
+        offsets.resize(N, 0);
+        let offsets = first::<N>(offsets);
+
+        let mut keyed: Vec<(Bounded<N>, V)> = Vec::with_capacity(pairs.size_hint().0);
+        for kv in bounded(pairs) {
+            *kv.0.index(offsets) += 1;
+            keyed.push(kv);
         }
-        const DESC: &'static Map<fn(u8) -> usize, Natural> =
-            &Map(conv,
-                 Natural { limit: ::std::u8::MAX as usize,
-                           is_unchecked: true, });
+        let runs = into_offsets(offsets);
+
+        let mut buffer = Box::new_uninit_slice(keyed.len());
+        for (k, v) in keyed {
+            let offset = k.index(offsets);
+            buffer[*offset].write(v);
+            *offset += 1;
+        }
+
+        // every value has been moved in
+        Ok(shared(buffer, runs))
+    }
+
+    // as `Natural::sparse`, but with keys that need no checks
+    fn sparse<K, V, I>(cx: &mut DiscriminationContext, pairs: I)
+        -> Result<Bucketed<V>, DiscriminationError>
+        where K: Into<Bounded<N>>,
+              I: Iterator,
+              I::Item: Into<(K, V)>
+    {
+        let desc = Natural { limit: N };
+        desc.prepare(cx)?;
+        let mut counts = Vec::new();
+        let mut values = Vec::with_capacity(pairs.size_hint().0);
+        {
+            let DiscriminationContext { ref mut slots, ref mut keys, .. } = *cx;
+            let slots = first::<N>(slots);
+            for (k, v) in bounded(pairs) {
+                Dense::fill(slots, keys, &mut counts, &mut values, k, v);
+            }
+        }
+        let order = desc.order(cx);
+        Ok(scattered(counts, &order, values))
+    }
+
+    // as `Natural::fill`, but with a key that needs no check
+    fn fill<V>(slots: &mut [usize; N],
+               keys: &mut Vec<usize>,
+               counts: &mut Vec<usize>,
+               values: &mut Vec<(usize, V)>,
+               k: Bounded<N>,
+               v: V) {
+        let slot = k.index(slots);
+        if *slot == UNTOUCHED {
+            *slot = counts.len();
+            keys.push(k.get());
+            counts.push(0);
+        }
+        counts[*slot] += 1;
+        values.push((*slot, v));
+    }
+
+    pub(crate) fn push_pending<V>(pending: &mut PendingBuckets<V>, k: Bounded<N>, v: V)
+                                  -> Result<(), DiscriminationError>
+    {
+        if pending.cx.slots.len() < N {
+            Natural { limit: N }.prepare(&mut pending.cx)?;
+        }
+        let DiscriminationContext { ref mut slots, ref mut keys, .. } = pending.cx;
+        Dense::fill(first::<N>(slots), keys, &mut pending.counts, &mut pending.values, k, v);
+        Ok(())
+    }
+}
+
+fn bounded<K, V, I, const N: usize>(pairs: I) -> impl Iterator<Item = (Bounded<N>, V)>
+    where K: Into<Bounded<N>>,
+          I: Iterator,
+          I::Item: Into<(K, V)>
+{
+    pairs.map(|kv| {
+                  let (k, v) = kv.into();
+                  (k.into(), v)
+              })
+}
+
+impl<'a, K: Into<Bounded<N>> + 'a, const N: usize> Discriminator<'a, K> for Dense<N> {
+    type Groups<V: 'a> = Bucketed<V>;
+
+    fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> Self::Groups<V>
+        where I: IntoIterator,
+              I::Item: Into<(K, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        or_panic(self.try_discriminate_sorted(pairs))
+    }
+
+    fn try_discriminate_sorted<V: 'a, I>(&'a self, pairs: I)
+        -> Result<Self::Groups<V>, DiscriminationError>
+        where I: IntoIterator,
+              I::Item: Into<(K, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        with_context(|cx| self.try_discriminate_sorted_in(cx, pairs))
+    }
+
+    fn try_discriminate_sorted_in<V: 'a, I>(&'a self, cx: &mut DiscriminationContext, pairs: I)
+        -> Result<Self::Groups<V>, DiscriminationError>
+        where I: IntoIterator,
+              I::Item: Into<(K, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        let pairs = pairs.into_iter();
+
+        // `Bounded` keys are below `N` by construction, so a lone one needs no
+        // check
+        if pairs.size_hint().1.is_some_and(|n| n <= 1) {
+            return Ok(single(pairs.map(|kv| kv.into().1).collect()));
+        }
+
+        if pairs.size_hint().0 >= N / 2 {
+            Dense::counted(cx, pairs)
+        } else {
+            Dense::sparse(cx, pairs)
+        }
+    }
+}
+
+/// Integer keys that a `Span` can discriminate, by way of their `i128` value.
+pub trait SpanKey: Copy {
+    fn to_i128(self) -> i128;
+}
+
+macro_rules! span_key {
+    ($($int:ty),*) => {
+        $(
+            impl SpanKey for $int {
+                fn to_i128(self) -> i128 {
+                    self as i128
+                }
+            }
+        )*
+    }
+}
+
+span_key!(i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, usize);
+
+/// Discriminates integer keys by their offset from the bottom of a span, so
+/// that keys such as years or IDs in a narrow window need only as many buckets
+/// as the window is wide.
+///
+/// The span is either given up front, in which case every key must lie within
+/// it, or found by scanning the input for its smallest and largest keys.  When
+/// the span is much wider than the input is long, the offsets are
+/// discriminated by radix instead.
+///
+/// Keys can be any primitive integer but `u128`, including `usize` and `isize`.
+#[derive(Debug,Copy,Clone,Default)]
+pub struct Span<K> {
+    pub bounds: Option<(K, K)>,
+}
+
+impl<K> Span<K> {
+    pub fn new(range: ops::Range<K>) -> Self {
+        Span { bounds: Some((range.start, range.end)) }
+    }
+
+    pub fn auto() -> Self {
+        Span { bounds: None }
+    }
+}
+
+impl<K: SpanKey> Span<K> {
+    // fails if there are bounds and `key` lies outside them
+    pub(crate) fn check(&self, key: i128) -> Result<(), DiscriminationError> {
+        match self.bounds.map(|(lo, hi)| (lo.to_i128(), hi.to_i128())) {
+            Some((start, end)) if key < start || end <= key => {
+                Err(DiscriminationError::OutOfSpan { key, start, end })
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<'a, K: SpanKey + 'a> Discriminator<'a, K> for Span<K> {
+    type Groups<V: 'a> = EitherGroups<Bucketed<V>, <U64 as Discriminator<'a, u64>>::Groups<V>>;
+
+    fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> Self::Groups<V>
+        where I: IntoIterator,
+              I::Item: Into<(K, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        or_panic(self.try_discriminate_sorted(pairs))
+    }
+
+    fn try_discriminate_sorted<V: 'a, I>(&'a self, pairs: I)
+        -> Result<Self::Groups<V>, DiscriminationError>
+        where I: IntoIterator,
+              I::Item: Into<(K, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        with_context(|cx| self.try_discriminate_sorted_in(cx, pairs))
+    }
+
+    fn try_discriminate_sorted_in<V: 'a, I>(&'a self, cx: &mut DiscriminationContext, pairs: I)
+        -> Result<Self::Groups<V>, DiscriminationError>
+        where I: IntoIterator,
+              I::Item: Into<(K, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        let pairs = pairs.into_iter();
+
+        if pairs.size_hint().1.is_some_and(|n| n <= 1) {
+            let values = pairs.map(|kv| {
+                                   let (k, v) = kv.into();
+                                   self.check(k.to_i128()).map(|()| v)
+                               })
+                              .collect::<Result<_, _>>()?;
+            return Ok(EitherGroups::Left(single(values)));
+        }
+
+        let pairs: Vec<(i128, V)> = pairs.map(|kv| {
+                                              let (k, v) = kv.into();
+                                              (k.to_i128(), v)
+                                          })
+                                         .collect();
+        if pairs.is_empty() {
+            return Ok(EitherGroups::Left(single(Vec::new())));
+        }
+
+        // offsets are taken in u128, as 128-bit keys can be further apart
+        // than any i128
+        let offset = |lo: i128, k: i128| k.wrapping_sub(lo) as u128;
+        let (lo, max_offset) = match self.bounds {
+            Some((lo, hi)) => {
+                pairs.iter().try_for_each(|kv| self.check(kv.0))?;
+                // some key lies between the bounds, so `lo < hi`
+                let (lo, hi) = (lo.to_i128(), hi.to_i128());
+                (lo, offset(lo, hi) - 1)
+            }
+            None => {
+                let lo = pairs.iter().map(|kv| kv.0).min().unwrap_or(0);
+                let hi = pairs.iter().map(|kv| kv.0).max().unwrap_or(0);
+                (lo, offset(lo, hi))
+            }
+        };
+
+        if max_offset / 4 >= pairs.len() as u128 {
+            let pairs = pairs.into_iter().map(move |(k, v)| (offset(lo, k), v));
+            if max_offset <= u64::MAX as u128 {
+                const DESC: &U64 = &U64;
+                return DESC.try_discriminate_sorted_in(cx, pairs.map(|(k, v)| (k as u64, v)))
+                           .map(EitherGroups::Right);
+            }
+            const DESC: &U128 = &U128;
+            return DESC.try_discriminate_sorted_in(cx, pairs).map(EitherGroups::Right);
+        }
+
+        // `max_offset` is under four times the number of keys, so fits in a usize
+        let desc = Natural::new(Ord::max(max_offset as usize + 1, 2));
+        desc.bdisc(cx, pairs.into_iter().map(|(k, v)| (offset(lo, k) as usize, v)))
+            .map(EitherGroups::Left)
+    }
+}
+
+#[derive(Debug,Copy,Clone,Default)]
+pub struct U8;
+
+impl U8 {
+    pub fn new() -> Self {
+        U8
+    }
+}
+
+impl<'a> Discriminator<'a, u8> for U8 {
+    type Groups<V: 'a> = Bucketed<V>;
+
+    fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> Self::Groups<V>
+        where I: IntoIterator,
+              I::Item: Into<(u8, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        const DESC: &Dense<{ u8::MAX as usize + 1 }> = &Dense;
         DESC.discriminate_sorted(pairs)
     }
+
+    fn try_discriminate_sorted_in<V: 'a, I>(&'a self, cx: &mut DiscriminationContext, pairs: I)
+        -> Result<Self::Groups<V>, DiscriminationError>
+        where I: IntoIterator,
+              I::Item: Into<(u8, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        const DESC: &Dense<{ u8::MAX as usize + 1 }> = &Dense;
+        DESC.try_discriminate_sorted_in(cx, pairs)
+    }
 }
 
 #[derive(Debug,Copy,Clone,Default)]
@@ -463,27 +1415,217 @@ impl U16 {
 }
 
 impl<'a> Discriminator<'a, u16> for U16 {
-    fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> DiscriminateSorted<'a, u16, V>
+    type Groups<V: 'a> = Bucketed<V>;
+
+    fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> Self::Groups<V>
+        where I: IntoIterator,
+              I::Item: Into<(u16, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        or_panic(self.try_discriminate_sorted(pairs))
+    }
+
+    fn try_discriminate_sorted<V: 'a, I>(&'a self, pairs: I)
+        -> Result<Self::Groups<V>, DiscriminationError>
         where I: IntoIterator,
               I::Item: Into<(u16, V)>,
               I::IntoIter: DoubleEndedIterator + 'a
     {
-        if cfg!(target_pointer_width = "8") {
-            unimplemented!(); // TODO: `Product`-based solution using `U8`
+        with_context(|cx| self.try_discriminate_sorted_in(cx, pairs))
+    }
+
+    fn try_discriminate_sorted_in<V: 'a, I>(&'a self, cx: &mut DiscriminationContext, pairs: I)
+        -> Result<Self::Groups<V>, DiscriminationError>
+        where I: IntoIterator,
+              I::Item: Into<(u16, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        #[allow(unexpected_cfgs)]
+        let is_tiny = cfg!(target_pointer_width = "8");
+        if is_tiny {
+            // TODO: `Product`-based solution using `U8`
+            Err(DiscriminationError::Unsupported("`U16` on targets with 8-bit pointers"))
         } else {
-            // when `const fn` support goes stable, this will be nicer
-            fn conv(k: u16) -> usize {
-                k as usize
+            const DESC: &Dense<{ u16::MAX as usize + 1 }> = &Dense;
+            DESC.try_discriminate_sorted_in(cx, pairs)
+        }
+    }
+}
+
+// sorted by radix rather than discriminated as a `Product` of their halves,
+// which is much faster for plain integer keys
+macro_rules! radix_discriminator {
+    ($name:ident, $uint:ty) => {
+        #[derive(Debug,Copy,Clone,Default)]
+        pub struct $name;
+
+        impl $name {
+            pub fn new() -> Self {
+                $name
             }
-            const DESC: &'static Map<fn(u16) -> usize, Natural> =
-                &Map(conv,
-                     Natural { limit: ::std::u16::MAX as usize,
-                               is_unchecked: true, });
-            DESC.discriminate_sorted(pairs)
         }
+
+        impl<'a> Discriminator<'a, $uint> for $name {
+            type Groups<V: 'a> = Bucketed<V>;
+
+            fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> Self::Groups<V>
+                where I: IntoIterator,
+                      I::Item: Into<($uint, V)>,
+                      I::IntoIter: DoubleEndedIterator + 'a
+            {
+                radix_sorted(pairs.into_iter().map(Into::into).collect())
+            }
+        }
+    }
+}
+
+radix_discriminator!(U32, u32);
+radix_discriminator!(U64, u64);
+radix_discriminator!(U128, u128);
+
+#[derive(Debug,Copy,Clone,Default)]
+pub struct Usize;
+
+impl Usize {
+    pub fn new() -> Self {
+        Usize
+    }
+}
+
+impl<'a> Discriminator<'a, usize> for Usize {
+    type Groups<V: 'a> = <U64 as Discriminator<'a, u64>>::Groups<V>;
+
+    fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> Self::Groups<V>
+        where I: IntoIterator,
+              I::Item: Into<(usize, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        or_panic(self.try_discriminate_sorted(pairs))
+    }
+
+    fn try_discriminate_sorted<V: 'a, I>(&'a self, pairs: I)
+        -> Result<Self::Groups<V>, DiscriminationError>
+        where I: IntoIterator,
+              I::Item: Into<(usize, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        with_context(|cx| self.try_discriminate_sorted_in(cx, pairs))
+    }
+
+    fn try_discriminate_sorted_in<V: 'a, I>(&'a self, cx: &mut DiscriminationContext, pairs: I)
+        -> Result<Self::Groups<V>, DiscriminationError>
+        where I: IntoIterator,
+              I::Item: Into<(usize, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        fn conv(k: usize) -> u64 {
+            k as u64
+        }
+        const DESC: &Map<fn(usize) -> u64, U64> = &Map(conv, U64);
+        DESC.try_discriminate_sorted_in(cx, pairs)
+    }
+}
+
+macro_rules! signed_discriminator {
+    ($name:ident, $int:ty, $uint:ty, $udesc:ident) => {
+        #[derive(Debug,Copy,Clone,Default)]
+        pub struct $name;
+
+        impl $name {
+            pub fn new() -> Self {
+                $name
+            }
+        }
+
+        impl<'a> Discriminator<'a, $int> for $name {
+            type Groups<V: 'a> = <$udesc as Discriminator<'a, $uint>>::Groups<V>;
+
+            fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> Self::Groups<V>
+                where I: IntoIterator,
+                      I::Item: Into<($int, V)>,
+                      I::IntoIter: DoubleEndedIterator + 'a
+            {
+                or_panic(self.try_discriminate_sorted(pairs))
+            }
+
+            fn try_discriminate_sorted<V: 'a, I>(&'a self, pairs: I)
+                -> Result<Self::Groups<V>, DiscriminationError>
+                where I: IntoIterator,
+                      I::Item: Into<($int, V)>,
+                      I::IntoIter: DoubleEndedIterator + 'a
+            {
+                with_context(|cx| self.try_discriminate_sorted_in(cx, pairs))
+            }
+
+            fn try_discriminate_sorted_in<V: 'a, I>(&'a self,
+                                                    cx: &mut DiscriminationContext,
+                                                    pairs: I)
+                -> Result<Self::Groups<V>, DiscriminationError>
+                where I: IntoIterator,
+                      I::Item: Into<($int, V)>,
+                      I::IntoIter: DoubleEndedIterator + 'a
+            {
+                // flipping the sign bit puts the negatives first, in order
+                fn conv(k: $int) -> $uint {
+                    (k as $uint) ^ (1 << (<$uint>::BITS - 1))
+                }
+                const DESC: &Map<fn($int) -> $uint, $udesc> = &Map(conv, $udesc);
+                DESC.try_discriminate_sorted_in(cx, pairs)
+            }
+        }
+    }
+}
+
+signed_discriminator!(I8, i8, u8, U8);
+signed_discriminator!(I16, i16, u16, U16);
+signed_discriminator!(I32, i32, u32, U32);
+signed_discriminator!(I64, i64, u64, U64);
+signed_discriminator!(I128, i128, u128, U128);
+
+#[derive(Debug,Copy,Clone,Default)]
+pub struct Isize;
+
+impl Isize {
+    pub fn new() -> Self {
+        Isize
+    }
+}
+
+impl<'a> Discriminator<'a, isize> for Isize {
+    type Groups<V: 'a> = <I64 as Discriminator<'a, i64>>::Groups<V>;
+
+    fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> Self::Groups<V>
+        where I: IntoIterator,
+              I::Item: Into<(isize, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        or_panic(self.try_discriminate_sorted(pairs))
+    }
+
+    fn try_discriminate_sorted<V: 'a, I>(&'a self, pairs: I)
+        -> Result<Self::Groups<V>, DiscriminationError>
+        where I: IntoIterator,
+              I::Item: Into<(isize, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        with_context(|cx| self.try_discriminate_sorted_in(cx, pairs))
+    }
+
+    fn try_discriminate_sorted_in<V: 'a, I>(&'a self, cx: &mut DiscriminationContext, pairs: I)
+        -> Result<Self::Groups<V>, DiscriminationError>
+        where I: IntoIterator,
+              I::Item: Into<(isize, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        fn conv(k: isize) -> i64 {
+            k as i64
+        }
+        const DESC: &Map<fn(isize) -> i64, I64> = &Map(conv, I64);
+        DESC.try_discriminate_sorted_in(cx, pairs)
     }
 }
 
+
 #[derive(Debug,Copy,Clone,Default)]
 pub struct Invert<D: ?Sized>(pub D);
 
@@ -511,29 +1653,241 @@ impl<D: ?Sized> AsMut<D> for Invert<D> {
     fn as_mut(&mut self) -> &mut D {
         &mut self.0
     }
-}
+}
+
+impl<'a, K: 'a, D: ?Sized> Discriminator<'a, K> for Invert<D>
+    where D: Discriminator<'a, K>
+{
+    type Groups<V: 'a> = Rev<D::Groups<V>>
+        where Self: 'a;
+
+    fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> Self::Groups<V>
+        where I: IntoIterator,
+              I::Item: Into<(K, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        self.0.discriminate_sorted(pairs).rev()
+    }
+
+    fn try_discriminate_sorted<V: 'a, I>(&'a self, pairs: I)
+        -> Result<Self::Groups<V>, DiscriminationError>
+        where I: IntoIterator,
+              I::Item: Into<(K, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        self.0.try_discriminate_sorted(pairs).map(Iterator::rev)
+    }
+
+    fn try_discriminate_sorted_in<V: 'a, I>(&'a self, cx: &mut DiscriminationContext, pairs: I)
+        -> Result<Self::Groups<V>, DiscriminationError>
+        where I: IntoIterator,
+              I::Item: Into<(K, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        self.0.try_discriminate_sorted_in(cx, pairs).map(Iterator::rev)
+    }
+
+    fn discriminate_sorted_in<V: 'a, I>(&'a self, cx: &mut DiscriminationContext, pairs: I)
+        -> Self::Groups<V>
+        where I: IntoIterator,
+              I::Item: Into<(K, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        self.0.discriminate_sorted_in(cx, pairs).rev()
+    }
+}
+
+/// Discriminates `Reverse<K>` keys in the opposite order to how the inner
+/// discriminator would discriminate the plain `K` keys.
+#[derive(Debug,Copy,Clone,Default)]
+pub struct Reversed<D: ?Sized>(pub D);
+
+impl<D> Reversed<D> {
+    pub fn new<I>(inner: I) -> Reversed<D>
+        where I: Into<D>
+    {
+        Reversed(inner.into())
+    }
+}
+
+fn unreverse<K, V>((k, v): (Reverse<K>, V)) -> (K, V) {
+    (k.0, v)
+}
+
+impl<'a, K: 'a, D: ?Sized> Discriminator<'a, Reverse<K>> for Reversed<D>
+    where D: Discriminator<'a, K>
+{
+    type Groups<V: 'a> = Rev<D::Groups<V>>
+        where Self: 'a;
+
+    fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> Self::Groups<V>
+        where I: IntoIterator,
+              I::Item: Into<(Reverse<K>, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        self.0
+            .discriminate_sorted(into_pairs(pairs).map(unreverse))
+            .rev()
+    }
+
+    fn try_discriminate_sorted<V: 'a, I>(&'a self, pairs: I)
+        -> Result<Self::Groups<V>, DiscriminationError>
+        where I: IntoIterator,
+              I::Item: Into<(Reverse<K>, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        self.0
+            .try_discriminate_sorted(into_pairs(pairs).map(unreverse))
+            .map(Iterator::rev)
+    }
+
+    fn try_discriminate_sorted_in<V: 'a, I>(&'a self, cx: &mut DiscriminationContext, pairs: I)
+        -> Result<Self::Groups<V>, DiscriminationError>
+        where I: IntoIterator,
+              I::Item: Into<(Reverse<K>, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        self.0
+            .try_discriminate_sorted_in(cx, into_pairs(pairs).map(unreverse))
+            .map(Iterator::rev)
+    }
+
+    fn discriminate_sorted_in<V: 'a, I>(&'a self, cx: &mut DiscriminationContext, pairs: I)
+        -> Self::Groups<V>
+        where I: IntoIterator,
+              I::Item: Into<(Reverse<K>, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        self.0
+            .discriminate_sorted_in(cx, into_pairs(pairs).map(unreverse))
+            .rev()
+    }
+}
+
+/// Discriminates `Wrapping<K>` keys as the inner discriminator would
+/// discriminate the plain `K` keys.
+#[derive(Debug,Copy,Clone,Default)]
+pub struct Wrapped<D: ?Sized>(pub D);
+
+impl<D> Wrapped<D> {
+    pub fn new<I>(inner: I) -> Wrapped<D>
+        where I: Into<D>
+    {
+        Wrapped(inner.into())
+    }
+}
+
+fn unwrap_wrapping<K, V>((k, v): (Wrapping<K>, V)) -> (K, V) {
+    (k.0, v)
+}
+
+impl<'a, K: 'a, D: ?Sized> Discriminator<'a, Wrapping<K>> for Wrapped<D>
+    where D: Discriminator<'a, K>
+{
+    type Groups<V: 'a> = D::Groups<V>
+        where Self: 'a;
+
+    fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> Self::Groups<V>
+        where I: IntoIterator,
+              I::Item: Into<(Wrapping<K>, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        self.0.discriminate_sorted(into_pairs(pairs).map(unwrap_wrapping))
+    }
+
+    fn try_discriminate_sorted<V: 'a, I>(&'a self, pairs: I)
+        -> Result<Self::Groups<V>, DiscriminationError>
+        where I: IntoIterator,
+              I::Item: Into<(Wrapping<K>, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        self.0.try_discriminate_sorted(into_pairs(pairs).map(unwrap_wrapping))
+    }
+
+    fn try_discriminate_sorted_in<V: 'a, I>(&'a self, cx: &mut DiscriminationContext, pairs: I)
+        -> Result<Self::Groups<V>, DiscriminationError>
+        where I: IntoIterator,
+              I::Item: Into<(Wrapping<K>, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        self.0.try_discriminate_sorted_in(cx, into_pairs(pairs).map(unwrap_wrapping))
+    }
 
-impl<'a, K: 'a, D: ?Sized> Discriminator<'a, K> for Invert<D>
-    where D: Discriminator<'a, K>
-{
-    fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> DiscriminateSorted<'a, K, V>
+    fn discriminate_sorted_in<V: 'a, I>(&'a self, cx: &mut DiscriminationContext, pairs: I)
+        -> Self::Groups<V>
         where I: IntoIterator,
-              I::Item: Into<(K, V)>,
+              I::Item: Into<(Wrapping<K>, V)>,
               I::IntoIter: DoubleEndedIterator + 'a
     {
-        let mut pairs = pairs.into_iter();
+        self.0.discriminate_sorted_in(cx, into_pairs(pairs).map(unwrap_wrapping))
+    }
+}
 
-        if pairs.size_hint().1.map_or(false, |n| n <= 1) {
-            return DiscriminateSorted(DiscriminateSortedImpl::One(pairs.next_back()
-                                                                       .map(|kv| kv.into().1)));
-        }
+/// Discriminates the standard nonzero integer types as the inner
+/// discriminator would discriminate the corresponding plain integers.
+#[derive(Debug,Copy,Clone,Default)]
+pub struct NonZero<D: ?Sized>(pub D);
 
-        DiscriminateSorted(DiscriminateSortedImpl::Invert(Box::new(
-            self.0.discriminate_sorted(pairs))))
+impl<D> NonZero<D> {
+    pub fn new<I>(inner: I) -> NonZero<D>
+        where I: Into<D>
+    {
+        NonZero(inner.into())
     }
 }
 
+macro_rules! nonzero_discriminator {
+    ($nonzero:ty, $int:ty) => {
+        impl<'a, D: ?Sized> Discriminator<'a, $nonzero> for NonZero<D>
+            where D: Discriminator<'a, $int>
+        {
+            type Groups<V: 'a> = D::Groups<V>
+                where Self: 'a;
+
+            fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> Self::Groups<V>
+                where I: IntoIterator,
+                      I::Item: Into<($nonzero, V)>,
+                      I::IntoIter: DoubleEndedIterator + 'a
+            {
+                self.0.discriminate_sorted(into_pairs(pairs).map(rekey(&<$nonzero>::get)))
+            }
+
+            fn try_discriminate_sorted<V: 'a, I>(&'a self, pairs: I)
+                -> Result<Self::Groups<V>, DiscriminationError>
+                where I: IntoIterator,
+                      I::Item: Into<($nonzero, V)>,
+                      I::IntoIter: DoubleEndedIterator + 'a
+            {
+                self.0.try_discriminate_sorted(into_pairs(pairs).map(rekey(&<$nonzero>::get)))
+            }
+
+            fn try_discriminate_sorted_in<V: 'a, I>(&'a self,
+                                                    cx: &mut DiscriminationContext,
+                                                    pairs: I)
+                -> Result<Self::Groups<V>, DiscriminationError>
+                where I: IntoIterator,
+                      I::Item: Into<($nonzero, V)>,
+                      I::IntoIter: DoubleEndedIterator + 'a
+            {
+                self.0
+                    .try_discriminate_sorted_in(cx, into_pairs(pairs).map(rekey(&<$nonzero>::get)))
+            }
+        }
+    }
+}
 
+nonzero_discriminator!(NonZeroU8, u8);
+nonzero_discriminator!(NonZeroU16, u16);
+nonzero_discriminator!(NonZeroU32, u32);
+nonzero_discriminator!(NonZeroU64, u64);
+nonzero_discriminator!(NonZeroU128, u128);
+nonzero_discriminator!(NonZeroUsize, usize);
+nonzero_discriminator!(NonZeroI8, i8);
+nonzero_discriminator!(NonZeroI16, i16);
+nonzero_discriminator!(NonZeroI32, i32);
+nonzero_discriminator!(NonZeroI64, i64);
+nonzero_discriminator!(NonZeroI128, i128);
+nonzero_discriminator!(NonZeroIsize, isize);
 
 #[derive(Debug,Copy,Clone,Default)]
 pub struct Map<F, D: ?Sized>(pub F, pub D);
@@ -563,25 +1917,167 @@ impl<'a, K: 'a, J: 'a, F, D: ?Sized> Discriminator<'a, K> for Map<F, D>
     where D: Discriminator<'a, J>,
           F: Fn(K) -> J
 {
-    fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> DiscriminateSorted<'a, K, V>
+    type Groups<V: 'a> = D::Groups<V>
+        where Self: 'a;
+
+    fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> Self::Groups<V>
         where I: IntoIterator,
               I::Item: Into<(K, V)>,
               I::IntoIter: DoubleEndedIterator + 'a
     {
-        let mut pairs = pairs.into_iter();
+        self.1.discriminate_sorted(into_pairs(pairs).map(rekey(&self.0)))
+    }
 
-        if pairs.size_hint().1.map_or(false, |n| n <= 1) {
-            return DiscriminateSorted(DiscriminateSortedImpl::One(pairs.next_back()
-                                                                       .map(|kv| kv.into().1)));
-        }
+    fn try_discriminate_sorted<V: 'a, I>(&'a self, pairs: I)
+        -> Result<Self::Groups<V>, DiscriminationError>
+        where I: IntoIterator,
+              I::Item: Into<(K, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        self.1.try_discriminate_sorted(into_pairs(pairs).map(rekey(&self.0)))
+    }
+
+    fn try_discriminate_sorted_in<V: 'a, I>(&'a self, cx: &mut DiscriminationContext, pairs: I)
+        -> Result<Self::Groups<V>, DiscriminationError>
+        where I: IntoIterator,
+              I::Item: Into<(K, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        self.1.try_discriminate_sorted_in(cx, into_pairs(pairs).map(rekey(&self.0)))
+    }
+
+    fn discriminate_sorted_in<V: 'a, I>(&'a self, cx: &mut DiscriminationContext, pairs: I)
+        -> Self::Groups<V>
+        where I: IntoIterator,
+              I::Item: Into<(K, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        self.1.discriminate_sorted_in(cx, into_pairs(pairs).map(rekey(&self.0)))
+    }
+}
+
+/// Like `Map`, but for key projections that can fail, such as parsing.
+///
+/// As a discriminator, records whose keys fail to project are gathered into a
+/// final group of rejects, after all of the others.  `discriminate_or_reject`
+/// also returns the errors, and `discriminate_or_fail` stops at the first.
+#[derive(Debug,Copy,Clone,Default)]
+pub struct TryMap<F, D: ?Sized>(pub F, pub D);
+
+impl<F, D> TryMap<F, D> {
+    pub fn new<I, G>(f: G, inner: I) -> TryMap<F, D>
+        where G: Into<F>,
+              I: Into<D>
+    {
+        TryMap(f.into(), inner.into())
+    }
+}
+
+impl<F, D: ?Sized> TryMap<F, D> {
+    #[allow(clippy::type_complexity)]
+    fn project<K, J, E, V, I>(&self, pairs: I) -> (Vec<(J, V)>, Vec<(E, V)>)
+        where F: Fn(K) -> Result<J, E>,
+              I: IntoIterator,
+              I::Item: Into<(K, V)>
+    {
+        let mut rejects = Vec::new();
+        let valid = pairs.into_iter()
+                         .filter_map(|kv| {
+                                         let (k, v) = kv.into();
+                                         match (self.0)(k) {
+                                             Ok(j) => Some((j, v)),
+                                             Err(e) => {
+                                                 rejects.push((e, v));
+                                                 None
+                                             }
+                                         }
+                                     })
+                         .collect();
+        (valid, rejects)
+    }
+
+    /// Discriminates the records whose keys project successfully, and returns
+    /// the rest alongside their errors.
+    pub fn discriminate_or_reject<'a, K, J: 'a, E, V: 'a, I>
+        (&'a self,
+         pairs: I)
+         -> (D::Groups<V>, Vec<(E, V)>)
+        where D: Discriminator<'a, J>,
+              F: Fn(K) -> Result<J, E>,
+              I: IntoIterator,
+              I::Item: Into<(K, V)>
+    {
+        let (valid, rejects) = self.project(pairs);
+        (self.1.discriminate_sorted(valid), rejects)
+    }
+
+    /// Discriminates the records if every key projects successfully, or
+    /// returns the first error.
+    pub fn discriminate_or_fail<'a, K, J: 'a, E, V: 'a, I>
+        (&'a self,
+         pairs: I)
+         -> Result<D::Groups<V>, E>
+        where D: Discriminator<'a, J>,
+              F: Fn(K) -> Result<J, E>,
+              I: IntoIterator,
+              I::Item: Into<(K, V)>
+    {
+        let valid = pairs.into_iter()
+                         .map(|kv| {
+                                  let (k, v) = kv.into();
+                                  (self.0)(k).map(|j| (j, v))
+                              })
+                         .collect::<Result<Vec<_>, E>>()?;
+        Ok(self.1.discriminate_sorted(valid))
+    }
+}
+
+/// The groups of `A`, followed by a group of rejects if there are any.
+pub type WithRejects<A, V> = iter::Chain<A, option::IntoIter<Group<V>>>;
+
+fn with_rejects<A, E, V>(groups: A, rejects: Vec<(E, V)>) -> WithRejects<A, V>
+    where A: Iterator<Item = Group<V>>
+{
+    let rejects = if rejects.is_empty() {
+        None
+    } else {
+        Some(Group::from(rejects.into_iter().map(|(_, v)| v).collect::<Vec<_>>()))
+    };
+    groups.chain(rejects)
+}
+
+impl<'a, K: 'a, J: 'a, E, F, D: ?Sized> Discriminator<'a, K> for TryMap<F, D>
+    where D: Discriminator<'a, J>,
+          F: Fn(K) -> Result<J, E>
+{
+    type Groups<V: 'a> = WithRejects<D::Groups<V>, V>
+        where Self: 'a;
+
+    fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> Self::Groups<V>
+        where I: IntoIterator,
+              I::Item: Into<(K, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        or_panic(self.try_discriminate_sorted(pairs))
+    }
 
-        DiscriminateSorted(DiscriminateSortedImpl::Opaque(Box::new(
-            self.1.discriminate_sorted(pairs.map(move |kv| {
-                let (k, v) = kv.into();
-                ((self.0)(k), v)
-            })).map(|group| {
-                DiscriminateSortedGroup(DiscriminateSortedGroupImpl::Opaque(Box::new(group)))
-            }))))
+    fn try_discriminate_sorted<V: 'a, I>(&'a self, pairs: I)
+        -> Result<Self::Groups<V>, DiscriminationError>
+        where I: IntoIterator,
+              I::Item: Into<(K, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        with_context(|cx| self.try_discriminate_sorted_in(cx, pairs))
+    }
+
+    fn try_discriminate_sorted_in<V: 'a, I>(&'a self, cx: &mut DiscriminationContext, pairs: I)
+        -> Result<Self::Groups<V>, DiscriminationError>
+        where I: IntoIterator,
+              I::Item: Into<(K, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        let (valid, rejects) = self.project(pairs);
+        Ok(with_rejects(self.1.try_discriminate_sorted_in(cx, valid)?, rejects))
     }
 }
 
@@ -612,45 +2108,58 @@ impl<L, R> Sum<L, R> {
     }
 }
 
+impl<L, R: ?Sized> Sum<L, R> {
+    #[allow(clippy::type_complexity)]
+    fn split<'a, J: 'a, K: 'a, V: 'a, I>(&'a self, pairs: I)
+        -> (impl DoubleEndedIterator<Item = (J, V)> + 'a,
+            impl DoubleEndedIterator<Item = (K, V)> + 'a)
+        where I: IntoIterator,
+              I::Item: Into<(Either<J, K>, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        split_either::<_, _, _, NonAtomic>(into_pairs(pairs).map(tag))
+    }
+}
+
 impl<'a, J: 'a, K: 'a, L, R: ?Sized> Discriminator<'a, Either<J, K>> for Sum<L, R>
     where L: Discriminator<'a, J>,
           R: Discriminator<'a, K>
 {
-    fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> DiscriminateSorted<'a, Either<J, K>, V>
+    type Groups<V: 'a> = SumGroups<L::Groups<V>, R::Groups<V>>
+        where Self: 'a;
+
+    fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> Self::Groups<V>
         where I: IntoIterator,
               I::Item: Into<(Either<J, K>, V)>,
               I::IntoIter: DoubleEndedIterator + 'a
     {
-        let mut pairs = pairs.into_iter();
+        or_panic(self.try_discriminate_sorted(pairs))
+    }
 
-        if pairs.size_hint().1.map_or(false, |n| n <= 1) {
-            return DiscriminateSorted(DiscriminateSortedImpl::One(pairs.next_back()
-                                                                       .map(|kv| kv.into().1)));
-        }
+    fn try_discriminate_sorted<V: 'a, I>(&'a self, pairs: I)
+        -> Result<Self::Groups<V>, DiscriminationError>
+        where I: IntoIterator,
+              I::Item: Into<(Either<J, K>, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        with_context(|cx| self.try_discriminate_sorted_in(cx, pairs))
+    }
 
-        let (left_pairs, right_pairs): (SplitEitherLeft<(J, V), (K, V), _>,
-                                        SplitEitherRight<(J, V), (K, V), _>) =
-            split_either(pairs.map(|kv| {
-                let (k, v) = kv.into();
-                match k {
-                    Left(kl) => Left((kl, v)),
-                    Right(kr) => Right((kr, v)),
-                }
-            }));
+    fn try_discriminate_sorted_in<V: 'a, I>(&'a self, cx: &mut DiscriminationContext, pairs: I)
+        -> Result<Self::Groups<V>, DiscriminationError>
+        where I: IntoIterator,
+              I::Item: Into<(Either<J, K>, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        let (left_pairs, right_pairs) = self.split(pairs);
         if self.is_right_biased {
-            DiscriminateSorted(DiscriminateSortedImpl::Opaque(Box::new(
-            self.right.discriminate_sorted(right_pairs).map(|group| {
-                DiscriminateSortedGroup(DiscriminateSortedGroupImpl::Opaque(Box::new(group)))
-            }).chain(self.left.discriminate_sorted(left_pairs).map(|group| {
-                DiscriminateSortedGroup(DiscriminateSortedGroupImpl::Opaque(Box::new(group)))
-            })))))
+            let right = self.right.try_discriminate_sorted_in(cx, right_pairs)?;
+            let left = self.left.try_discriminate_sorted_in(cx, left_pairs)?;
+            Ok(SumGroups::new(true, left, right))
         } else {
-            DiscriminateSorted(DiscriminateSortedImpl::Opaque(Box::new(
-            self.left.discriminate_sorted(left_pairs).map(|group| {
-                DiscriminateSortedGroup(DiscriminateSortedGroupImpl::Opaque(Box::new(group)))
-            }).chain(self.right.discriminate_sorted(right_pairs).map(|group| {
-                DiscriminateSortedGroup(DiscriminateSortedGroupImpl::Opaque(Box::new(group)))
-            })))))
+            let left = self.left.try_discriminate_sorted_in(cx, left_pairs)?;
+            let right = self.right.try_discriminate_sorted_in(cx, right_pairs)?;
+            Ok(SumGroups::new(false, left, right))
         }
     }
 }
@@ -682,44 +2191,457 @@ impl<L, R> Product<L, R> {
     }
 }
 
+impl<L, R: ?Sized> Product<L, R> {
+    // discriminates by the outer component with `cx`, or with a context of its
+    // own if there's none, which the inner component then refines each group
+    // with as iteration reaches it
+    fn lazily<'a, J: 'a, K: 'a, V: 'a, I>(&'a self,
+                                          cx: Option<&mut DiscriminationContext>,
+                                          pairs: I)
+                                          -> <Self as Discriminator<'a, (J, K)>>::Groups<V>
+        where L: Discriminator<'a, J>,
+              R: Discriminator<'a, K>,
+              I: IntoIterator,
+              I::Item: Into<((J, K), V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        let mut own = take_context();
+        let pairs = into_pairs(pairs);
+        if self.is_right_biased {
+            let outer = self.right
+                            .discriminate_sorted_in(cx.unwrap_or(&mut own), pairs.map(nest_right));
+            EitherGroups::Right(ProductGroups::lazy(&self.left, outer, own))
+        } else {
+            let outer = self.left
+                            .discriminate_sorted_in(cx.unwrap_or(&mut own), pairs.map(nest_left));
+            EitherGroups::Left(ProductGroups::lazy(&self.right, outer, own))
+        }
+    }
+}
+
+/// Refines the groups of one component by the other as iteration reaches
+/// them, except through the `try_` methods, which refine them all up front so
+/// as to report bad keys in either component.
 impl<'a, J: 'a, K: 'a, L, R: ?Sized> Discriminator<'a, (J, K)> for Product<L, R>
     where L: Discriminator<'a, J>,
           R: Discriminator<'a, K>
 {
-    fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> DiscriminateSorted<'a, (J, K), V>
+    type Groups<V: 'a> = EitherGroups<ProductGroups<'a, K, V, R, L::Groups<(K, V)>>,
+                                      ProductGroups<'a, J, V, L, R::Groups<(J, V)>>>
+        where Self: 'a;
+
+    fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> Self::Groups<V>
         where I: IntoIterator,
               I::Item: Into<((J, K), V)>,
               I::IntoIter: DoubleEndedIterator + 'a
     {
-        let mut pairs = pairs.into_iter();
+        self.lazily(None, pairs)
+    }
 
-        if pairs.size_hint().1.map_or(false, |n| n <= 1) {
-            return DiscriminateSorted(DiscriminateSortedImpl::One(pairs.next_back()
-                                                                       .map(|kv| kv.into().1)));
-        }
+    fn try_discriminate_sorted<V: 'a, I>(&'a self, pairs: I)
+        -> Result<Self::Groups<V>, DiscriminationError>
+        where I: IntoIterator,
+              I::Item: Into<((J, K), V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        with_context(|cx| self.try_discriminate_sorted_in(cx, pairs))
+    }
 
+    fn try_discriminate_sorted_in<V: 'a, I>(&'a self, cx: &mut DiscriminationContext, pairs: I)
+        -> Result<Self::Groups<V>, DiscriminationError>
+        where I: IntoIterator,
+              I::Item: Into<((J, K), V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        let pairs = into_pairs(pairs);
         if self.is_right_biased {
-            DiscriminateSorted(DiscriminateSortedImpl::Opaque(Box::new(
-            self.right.discriminate_sorted(pairs.map(|kv| {
-                let ((kl, kr), v) = kv.into();
-                (kr, (kl, v))
-            })).map(|group| -> DiscriminateSortedGroup<'a, (J, K), (J, V)> {
-                DiscriminateSortedGroup(DiscriminateSortedGroupImpl::Opaque(Box::new(group)))
-            }).flat_map(move |pairs_stripped| {
-                        self.left.discriminate_sorted(pairs_stripped).map(|group| {
-                DiscriminateSortedGroup(DiscriminateSortedGroupImpl::Opaque(Box::new(group)))
-                        }) }))))
+            let groups = self.right
+                             .try_discriminate_sorted_in(cx, pairs.map(nest_right))?
+                             .map(|group| self.left.try_discriminate_sorted_in(cx, group))
+                             .collect::<Result<_, _>>()?;
+            Ok(EitherGroups::Right(ProductGroups::refined(&self.left, groups)))
         } else {
-            DiscriminateSorted(DiscriminateSortedImpl::Opaque(Box::new(
-            self.left.discriminate_sorted(pairs.map(|kv| {
-                let ((kl, kr), v) = kv.into();
-                (kl, (kr, v))
-            })).map(|group| -> DiscriminateSortedGroup<'a, (J, K), (K, V)> {
-                DiscriminateSortedGroup(DiscriminateSortedGroupImpl::Opaque(Box::new(group)))
-            }).flat_map(move |pairs_stripped| {
-                        self.right.discriminate_sorted(pairs_stripped).map(|group| {
-                DiscriminateSortedGroup(DiscriminateSortedGroupImpl::Opaque(Box::new(group)))
-                        }) }))))
+            let groups = self.left
+                             .try_discriminate_sorted_in(cx, pairs.map(nest_left))?
+                             .map(|group| self.right.try_discriminate_sorted_in(cx, group))
+                             .collect::<Result<_, _>>()?;
+            Ok(EitherGroups::Left(ProductGroups::refined(&self.right, groups)))
+        }
+    }
+
+    fn discriminate_sorted_in<V: 'a, I>(&'a self, cx: &mut DiscriminationContext, pairs: I)
+        -> Self::Groups<V>
+        where I: IntoIterator,
+              I::Item: Into<((J, K), V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        self.lazily(Some(cx), pairs)
+    }
+}
+
+/// The groups of a `Product`: those of an outer discrimination by one
+/// component, each refined by `D`, the discriminator of the other.
+///
+/// Like `Flatten`, it only holds the refinements of the outer groups it's
+/// taking groups from at either end.
+pub struct ProductGroups<'a, K: 'a, V: 'a, D: ?Sized + 'a, O>
+    where D: Discriminator<'a, K>
+{
+    inner: &'a D,
+    outer: Outer<O, D::Groups<V>>,
+    front: Option<D::Groups<V>>,
+    back: Option<D::Groups<V>>,
+    marker: PhantomData<fn() -> K>,
+}
+
+// the outer groups, either still to be refined, with the context to refine
+// them in, or refined already
+enum Outer<O, G> {
+    Lazy(O, DiscriminationContext),
+    Refined(vec::IntoIter<G>),
+}
+
+impl<'a, K: 'a, V: 'a, D: ?Sized + 'a, O> ProductGroups<'a, K, V, D, O>
+    where D: Discriminator<'a, K>,
+          O: DoubleEndedIterator<Item = Group<(K, V)>>
+{
+    fn lazy(inner: &'a D, outer: O, cx: DiscriminationContext) -> Self {
+        ProductGroups { inner,
+                        outer: Outer::Lazy(outer, cx),
+                        front: None,
+                        back: None,
+                        marker: PhantomData, }
+    }
+
+    fn refined(inner: &'a D, groups: Vec<D::Groups<V>>) -> Self {
+        ProductGroups { inner,
+                        outer: Outer::Refined(groups.into_iter()),
+                        front: None,
+                        back: None,
+                        marker: PhantomData, }
+    }
+
+    // the refinement of the next outer group from the front or the back
+    fn refine(&mut self, is_back: bool) -> Option<D::Groups<V>> {
+        let inner = self.inner;
+        match self.outer {
+            Outer::Lazy(ref mut groups, ref mut cx) => {
+                let group = if is_back { groups.next_back() } else { groups.next() }?;
+                Some(inner.discriminate_sorted_in(cx, group))
+            }
+            Outer::Refined(ref mut groups) => {
+                if is_back {
+                    groups.next_back()
+                } else {
+                    groups.next()
+                }
+            }
+        }
+    }
+}
+
+impl<'a, K: 'a, V: 'a, D: ?Sized + 'a, O> Iterator for ProductGroups<'a, K, V, D, O>
+    where D: Discriminator<'a, K>,
+          O: DoubleEndedIterator<Item = Group<(K, V)>>
+{
+    type Item = Group<V>;
+
+    fn next(&mut self) -> Option<Group<V>> {
+        loop {
+            if let Some(group) = self.front.as_mut().and_then(Iterator::next) {
+                return Some(group);
+            }
+            // the spent refinement goes first, so that its context can be
+            // reused by the next
+            self.front = None;
+            match self.refine(false) {
+                Some(groups) => self.front = Some(groups),
+                None => return self.back.as_mut().and_then(Iterator::next),
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let held = |groups: &Option<D::Groups<V>>| groups.as_ref().map_or(0, |g| g.size_hint().0);
+        (held(&self.front).saturating_add(held(&self.back)), None)
+    }
+}
+
+impl<'a, K: 'a, V: 'a, D: ?Sized + 'a, O> DoubleEndedIterator for ProductGroups<'a, K, V, D, O>
+    where D: Discriminator<'a, K>,
+          O: DoubleEndedIterator<Item = Group<(K, V)>>
+{
+    fn next_back(&mut self) -> Option<Group<V>> {
+        loop {
+            if let Some(group) = self.back.as_mut().and_then(DoubleEndedIterator::next_back) {
+                return Some(group);
+            }
+            self.back = None;
+            match self.refine(true) {
+                Some(groups) => self.back = Some(groups),
+                None => return self.front.as_mut().and_then(DoubleEndedIterator::next_back),
+            }
+        }
+    }
+}
+
+impl<'a, K: 'a, V: 'a, D: ?Sized + 'a, O> Drop for ProductGroups<'a, K, V, D, O>
+    where D: Discriminator<'a, K>
+{
+    fn drop(&mut self) {
+        if let Outer::Lazy(_, ref mut cx) = self.outer {
+            give_back(mem::take(cx));
+        }
+    }
+}
+
+#[derive(Debug,Copy,Clone,Default)]
+pub struct Prefix<D: ?Sized> {
+    pub depth: usize,
+    pub element: D,
+}
+
+impl<D> Prefix<D> {
+    pub fn new<E>(element: E, depth: usize) -> Prefix<D>
+        where E: Into<D>
+    {
+        Prefix { depth,
+                 element: element.into(), }
+    }
+
+    pub fn unbounded<E>(element: E) -> Prefix<D>
+        where E: Into<D>
+    {
+        Prefix::new(element, usize::MAX)
+    }
+}
+
+impl<D: ?Sized> Prefix<D> {
+    /// Discriminates `pairs` by the first `depth` elements of their keys,
+    /// returning the result as a trie whose leaves can be refined later.
+    pub fn trie<'a, K, V: 'a, I>(&'a self, pairs: I) -> PrefixTrie<K::Item, K::IntoIter, V>
+        where I: IntoIterator,
+              I::Item: Into<(K, V)>,
+              K: IntoIterator,
+              K::Item: Clone + 'a,
+              K::IntoIter: 'a,
+              D: Discriminator<'a, K::Item>
+    {
+        let mut trie = PrefixTrie::default();
+        trie.pending.extend(pairs.into_iter().map(|kv| {
+            let (k, v) = kv.into();
+            (k.into_iter(), v)
+        }));
+        trie.refine(&self.element, self.depth);
+        trie
+    }
+}
+
+impl<'a, K: 'a, D: ?Sized> Discriminator<'a, K> for Prefix<D>
+    where K: IntoIterator,
+          K::Item: 'a,
+          K::IntoIter: 'a,
+          D: Discriminator<'a, K::Item>
+{
+    type Groups<V: 'a> = Bucketed<V>
+        where Self: 'a;
+
+    fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> Self::Groups<V>
+        where I: IntoIterator,
+              I::Item: Into<(K, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        or_panic(self.try_discriminate_sorted(pairs))
+    }
+
+    fn try_discriminate_sorted<V: 'a, I>(&'a self, pairs: I)
+        -> Result<Self::Groups<V>, DiscriminationError>
+        where I: IntoIterator,
+              I::Item: Into<(K, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        with_context(|cx| self.try_discriminate_sorted_in(cx, pairs))
+    }
+
+    fn try_discriminate_sorted_in<V: 'a, I>(&'a self, cx: &mut DiscriminationContext, pairs: I)
+        -> Result<Self::Groups<V>, DiscriminationError>
+        where I: IntoIterator,
+              I::Item: Into<(K, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        let pairs = pairs.into_iter();
+
+        // depth-first, so that groups come out in order without recursing
+        let mut values = Vec::new();
+        let mut runs = Vec::new();
+        let mut stack = vec![(0, pairs.map(|kv| {
+                                          let (k, v) = kv.into();
+                                          (k.into_iter(), v)
+                                      })
+                                      .collect::<Vec<_>>())];
+        while let Some((depth, members)) = stack.pop() {
+            let start = values.len();
+            if members.len() <= 1 || depth >= self.depth {
+                values.extend(members.into_iter().map(|sv| sv.1));
+                runs.push((start, values.len()));
+                continue;
+            }
+
+            let mut heads = Vec::new();
+            for (mut rest, v) in members {
+                match rest.next() {
+                    None => values.push(v),
+                    Some(e) => heads.push((e, (rest, v))),
+                }
+            }
+            runs.push((start, values.len()));
+            let children: Vec<Vec<_>> = self.element
+                                            .try_discriminate_sorted_in(cx, heads)?
+                                            .map(Iterator::collect)
+                                            .collect();
+            stack.extend(children.into_iter().rev().map(|child| (depth + 1, child)));
+        }
+        Ok(grouped(values, runs))
+    }
+}
+
+/// A node of the trie built by `Prefix::trie`.
+///
+/// A node either has been discriminated, in which case `terminal` holds the
+/// values whose keys end here and `children` holds one subtrie per distinct
+/// next element (in discriminator order), or it is still `pending`, holding
+/// each value alongside the unconsumed remainder of its key.
+///
+/// Tries are torn down without recursing, however deep they are, so their
+/// parts are reached through accessors and `into_parts` rather than fields.
+#[derive(Debug,Clone)]
+pub struct PrefixTrie<E, S, V> {
+    terminal: Vec<V>,
+    children: Vec<(E, PrefixTrie<E, S, V>)>,
+    pending: Vec<(S, V)>,
+}
+
+impl<E, S, V> Default for PrefixTrie<E, S, V> {
+    fn default() -> Self {
+        PrefixTrie { terminal: Vec::new(),
+                     children: Vec::new(),
+                     pending: Vec::new(), }
+    }
+}
+
+impl<E, S, V> PrefixTrie<E, S, V> {
+    pub fn len(&self) -> usize {
+        let mut len = 0;
+        let mut stack = vec![self];
+        while let Some(node) = stack.pop() {
+            len += node.terminal.len() + node.pending.len();
+            stack.extend(node.children.iter().map(|child| &child.1));
+        }
+        len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// The values whose keys end at this node.
+    pub fn terminal(&self) -> &[V] {
+        &self.terminal
+    }
+
+    /// One subtrie per distinct next element, in discriminator order.
+    pub fn children(&self) -> &[(E, PrefixTrie<E, S, V>)] {
+        &self.children
+    }
+
+    pub fn children_mut(&mut self) -> &mut [(E, PrefixTrie<E, S, V>)] {
+        &mut self.children
+    }
+
+    /// The values not yet discriminated here, each alongside the rest of its
+    /// key.
+    pub fn pending(&self) -> &[(S, V)] {
+        &self.pending
+    }
+
+    /// This node's terminal values, children and pending values, in that
+    /// order.
+    #[allow(clippy::type_complexity)]
+    pub fn into_parts(mut self) -> (Vec<V>, Vec<(E, PrefixTrie<E, S, V>)>, Vec<(S, V)>) {
+        (mem::take(&mut self.terminal),
+         mem::take(&mut self.children),
+         mem::take(&mut self.pending))
+    }
+
+    /// Continues discriminating this node's pending values for up to `depth`
+    /// more elements of their keys.  Pending values further down the trie are
+    /// left alone.
+    pub fn refine<'a, D>(&mut self, element: &'a D, depth: usize)
+        where S: Iterator<Item = E> + 'a,
+              E: Clone + 'a,
+              V: 'a,
+              D: ?Sized + Discriminator<'a, E>
+    {
+        // keys can be longer than the call stack is deep, so nodes are refined
+        // from a stack of their own
+        let mut stack = vec![(self, depth)];
+        while let Some((node, depth)) = stack.pop() {
+            if depth == 0 || node.pending.is_empty() {
+                continue;
+            }
+
+            let mut heads = Vec::new();
+            for (mut rest, v) in node.pending.drain(..) {
+                match rest.next() {
+                    None => node.terminal.push(v),
+                    Some(e) => heads.push((e.clone(), (e, rest, v))),
+                }
+            }
+            let first = node.children.len();
+            for group in element.discriminate_sorted(heads) {
+                let mut label = None;
+                let mut child = PrefixTrie::default();
+                for (e, rest, v) in group {
+                    label = label.or(Some(e));
+                    child.pending.push((rest, v));
+                }
+                if let Some(label) = label {
+                    node.children.push((label, child));
+                }
+            }
+            stack.extend(node.children[first..].iter_mut().map(|child| (&mut child.1, depth - 1)));
+        }
+    }
+
+    /// All values in the trie, in order.
+    pub fn into_values(mut self) -> Vec<V> {
+        let mut values = Vec::new();
+        let mut stack = vec![mem::take(&mut self)];
+        while let Some(mut node) = stack.pop() {
+            values.append(&mut node.terminal);
+            // the pending values come after the children's, as a node of their own
+            if node.is_pending() {
+                let pending = node.pending.drain(..).map(|sv| sv.1).collect();
+                stack.push(PrefixTrie { terminal: pending,
+                                        children: Vec::new(),
+                                        pending: Vec::new(), });
+            }
+            stack.extend(node.children.drain(..).rev().map(|child| child.1));
+        }
+        values
+    }
+}
+
+impl<E, S, V> Drop for PrefixTrie<E, S, V> {
+    // tears deep tries down from a stack of its own, rather than recursing
+    fn drop(&mut self) {
+        let mut stack: Vec<_> = self.children.drain(..).map(|child| child.1).collect();
+        while let Some(mut node) = stack.pop() {
+            stack.extend(node.children.drain(..).map(|child| child.1));
         }
     }
 }
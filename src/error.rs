@@ -0,0 +1,46 @@
+use alloc::collections::TryReserveError;
+use core::error::Error;
+use core::fmt;
+
+/// The ways in which discrimination can fail on keys it can't handle.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub enum DiscriminationError {
+    /// A key was at or above the limit of a `Natural`.
+    OutOfRange { key: usize, limit: usize },
+    /// A key was outside the given bounds of a `Span`.
+    OutOfSpan { key: i128, start: i128, end: i128 },
+    /// The buckets couldn't be allocated.
+    AllocationFailed(TryReserveError),
+    /// The discriminator can't be used on this target or in this configuration.
+    Unsupported(&'static str),
+}
+
+impl fmt::Display for DiscriminationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            DiscriminationError::OutOfRange { key, limit } => {
+                write!(f, "key {} is out of range for limit {}", key, limit)
+            }
+            DiscriminationError::OutOfSpan { key, start, end } => {
+                write!(f, "key {} is outside of span {}..{}", key, start, end)
+            }
+            DiscriminationError::AllocationFailed(ref e) => write!(f, "{}", e),
+            DiscriminationError::Unsupported(what) => write!(f, "unsupported: {}", what),
+        }
+    }
+}
+
+impl Error for DiscriminationError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            DiscriminationError::AllocationFailed(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<TryReserveError> for DiscriminationError {
+    fn from(e: TryReserveError) -> Self {
+        DiscriminationError::AllocationFailed(e)
+    }
+}
@@ -0,0 +1,390 @@
+//! Discrimination of inputs too large to hold in memory at once.
+//!
+//! Records are spilled to files partitioned by the leading digits of their
+//! keys, as a `Radix` breaks them down, and each partition small enough to
+//! fit in the memory budget is then discriminated in memory.  Partitions that
+//! are still too large are split again by the digits that follow.
+
+use discriminator::{Discriminator, Group};
+use error::DiscriminationError;
+use sort::Radix;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Keys and values that can be written to spill files and read back.
+pub trait Spill: Sized {
+    fn spill<W: Write>(&self, out: &mut W) -> io::Result<()>;
+
+    fn unspill<R: Read>(input: &mut R) -> io::Result<Self>;
+}
+
+macro_rules! spill_int {
+    ($int:ty) => {
+        impl Spill for $int {
+            fn spill<W: Write>(&self, out: &mut W) -> io::Result<()> {
+                out.write_all(&self.to_le_bytes())
+            }
+
+            fn unspill<R: Read>(input: &mut R) -> io::Result<Self> {
+                let mut bytes = [0; (<$int>::BITS / 8) as usize];
+                input.read_exact(&mut bytes)?;
+                Ok(<$int>::from_le_bytes(bytes))
+            }
+        }
+    }
+}
+
+spill_int!(u8);
+spill_int!(u16);
+spill_int!(u32);
+spill_int!(u64);
+spill_int!(u128);
+spill_int!(i8);
+spill_int!(i16);
+spill_int!(i32);
+spill_int!(i64);
+spill_int!(i128);
+
+fn invalid<E>(e: E) -> io::Error
+    where E: Into<Box<dyn Error + Send + Sync>>
+{
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+// keys that the discriminator can't handle
+fn invalid_input(e: DiscriminationError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, e)
+}
+
+// spilled as 64 bits, so that files can be read back on other targets
+impl Spill for usize {
+    fn spill<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        (*self as u64).spill(out)
+    }
+
+    fn unspill<R: Read>(input: &mut R) -> io::Result<Self> {
+        usize::try_from(u64::unspill(input)?).map_err(invalid)
+    }
+}
+
+impl Spill for isize {
+    fn spill<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        (*self as i64).spill(out)
+    }
+
+    fn unspill<R: Read>(input: &mut R) -> io::Result<Self> {
+        isize::try_from(i64::unspill(input)?).map_err(invalid)
+    }
+}
+
+impl Spill for bool {
+    fn spill<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        (*self as u8).spill(out)
+    }
+
+    fn unspill<R: Read>(input: &mut R) -> io::Result<Self> {
+        Ok(u8::unspill(input)? != 0)
+    }
+}
+
+impl Spill for () {
+    fn spill<W: Write>(&self, _: &mut W) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn unspill<R: Read>(_: &mut R) -> io::Result<Self> {
+        Ok(())
+    }
+}
+
+impl<T: Spill> Spill for Vec<T> {
+    fn spill<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        self.len().spill(out)?;
+        self.iter().try_for_each(|x| x.spill(out))
+    }
+
+    fn unspill<R: Read>(input: &mut R) -> io::Result<Self> {
+        let len = usize::unspill(input)?;
+        (0..len).map(|_| T::unspill(input)).collect()
+    }
+}
+
+impl Spill for String {
+    fn spill<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        self.len().spill(out)?;
+        out.write_all(self.as_bytes())
+    }
+
+    fn unspill<R: Read>(input: &mut R) -> io::Result<Self> {
+        let len = usize::unspill(input)?;
+        let mut bytes = vec![0; len];
+        input.read_exact(&mut bytes)?;
+        String::from_utf8(bytes).map_err(invalid)
+    }
+}
+
+impl<A: Spill, B: Spill> Spill for (A, B) {
+    fn spill<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        self.0.spill(out)?;
+        self.1.spill(out)
+    }
+
+    fn unspill<R: Read>(input: &mut R) -> io::Result<Self> {
+        let a = A::unspill(input)?;
+        Ok((a, B::unspill(input)?))
+    }
+}
+
+/// Drives a discriminator over inputs larger than memory, spilling records
+/// to files in `dir` so that no more than about `budget` bytes of them are
+/// held at once.
+///
+/// A partition is only discriminated in memory once its records, counted at
+/// their own size as well as the bytes they spilled to, fit in `budget`.  The
+/// discrimination's own buffers come on top of that, and a single group still
+/// has to fit in memory, since it's handed over whole.
+#[derive(Debug,Clone)]
+pub struct External {
+    pub budget: usize,
+    pub dir: PathBuf,
+}
+
+impl External {
+    pub fn new<P>(budget: usize, dir: P) -> Self
+        where P: Into<PathBuf>
+    {
+        External { budget,
+                   dir: dir.into(), }
+    }
+
+    /// Discriminates `pairs` by `desc`, passing every group to `f` in key
+    /// order.
+    ///
+    /// The digits of `desc` must order keys the same way it discriminates
+    /// them, as those of every `Radix` in this crate do.  Keys that `desc`
+    /// can't handle are reported as `InvalidInput`.
+    pub fn discriminate<'a, K, V, D, I, F>(&self, desc: &'a D, pairs: I, mut f: F) -> io::Result<()>
+        where K: Spill + 'a,
+              V: Spill + 'a,
+              D: ?Sized + Radix<K> + Discriminator<'a, K>,
+              I: IntoIterator<Item = (K, V)>,
+              F: FnMut(Group<V>)
+    {
+        let end = if desc.digits() > 0 { desc.radix(0) } else { 0 };
+        let level = Level::new(desc, 0, 0, end);
+        self.split(desc, level, &mut pairs.into_iter().map(Ok), &mut f)
+    }
+
+    // partitions `records` by their digits at `level`, then discriminates the
+    // partitions that fit and splits the rest further
+    fn split<'a, K, V, D>(&self,
+                          desc: &'a D,
+                          level: Level,
+                          records: &mut dyn Iterator<Item = io::Result<(K, V)>>,
+                          f: &mut dyn FnMut(Group<V>))
+                          -> io::Result<()>
+        where K: Spill + 'a,
+              V: Spill + 'a,
+              D: ?Sized + Radix<K> + Discriminator<'a, K>
+    {
+        if level.position == desc.digits() {
+            // every key is the same, so there's nothing left to split by
+            return discriminate(desc, records.collect::<io::Result<_>>()?, f);
+        }
+
+        let mut parts: Vec<Partition> = (0..level.fanout()).map(|_| Partition::default()).collect();
+        let mut buffered = 0;
+        let mut has_spilled = false;
+        for kv in records {
+            let (k, v) = kv?;
+            let digit = desc.try_digit(&k, level.position).map_err(invalid_input)?;
+            let part = &mut parts[level.part(digit)];
+            let len = part.tail.len();
+            k.spill(&mut part.tail)?;
+            v.spill(&mut part.tail)?;
+            part.count += 1;
+            part.bytes += part.tail.len() - len;
+            buffered += part.tail.len() - len;
+            if buffered > self.budget {
+                for part in &mut parts {
+                    part.flush(&self.dir)?;
+                }
+                buffered = 0;
+                has_spilled = true;
+            }
+        }
+
+        if !has_spilled {
+            for (i, part) in parts.iter().enumerate() {
+                self.take(desc, level, i, part, &mut read(&part.tail[..], part.count), f)?;
+            }
+            return Ok(());
+        }
+
+        // free the buffers before any partition is read back
+        for part in &mut parts {
+            part.flush(&self.dir)?;
+        }
+        for (i, part) in parts.iter_mut().enumerate() {
+            let mut file = match part.file.take() {
+                Some(file) => file,
+                None => continue,
+            };
+            file.file.seek(SeekFrom::Start(0))?;
+            let mut records = read(BufReader::new(&file.file), part.count);
+            self.take(desc, level, i, part, &mut records, f)?;
+        }
+        Ok(())
+    }
+
+    // discriminates the records of partition `i` in memory if they fit, or
+    // splits them further
+    fn take<'a, K, V, D>(&self,
+                         desc: &'a D,
+                         level: Level,
+                         i: usize,
+                         part: &Partition,
+                         records: &mut dyn Iterator<Item = io::Result<(K, V)>>,
+                         f: &mut dyn FnMut(Group<V>))
+                         -> io::Result<()>
+        where K: Spill + 'a,
+              V: Spill + 'a,
+              D: ?Sized + Radix<K> + Discriminator<'a, K>
+    {
+        if part.size::<K, V>() <= self.budget {
+            discriminate(desc, records.collect::<io::Result<_>>()?, f)
+        } else {
+            self.split(desc, level.narrow(desc, i), records, f)
+        }
+    }
+}
+
+// files are split this many ways at most, so as not to run out of handles
+const FANOUT: usize = 256;
+
+// the digits from `start` to `end` at `position`
+#[derive(Debug,Copy,Clone)]
+struct Level {
+    position: usize,
+    start: usize,
+    end: usize,
+}
+
+impl Level {
+    // skips over positions with only one digit left to split by
+    fn new<K, D>(desc: &D, mut position: usize, mut start: usize, mut end: usize) -> Self
+        where D: ?Sized + Radix<K>
+    {
+        while end - start <= 1 && position < desc.digits() {
+            position += 1;
+            start = 0;
+            end = if position < desc.digits() { desc.radix(position) } else { 0 };
+        }
+        Level { position,
+                start,
+                end, }
+    }
+
+    fn fanout(&self) -> usize {
+        Ord::min(self.end - self.start, FANOUT)
+    }
+
+    // digits are spread over the partitions evenly and in order
+    fn part(&self, digit: usize) -> usize {
+        ((digit - self.start) as u128 * self.fanout() as u128 / (self.end - self.start) as u128)
+            as usize
+    }
+
+    // the first digit of partition `i`
+    fn bound(&self, i: usize) -> usize {
+        let width = (self.end - self.start) as u128;
+        self.start + (i as u128 * width).div_ceil(self.fanout() as u128) as usize
+    }
+
+    fn narrow<K, D>(&self, desc: &D, i: usize) -> Self
+        where D: ?Sized + Radix<K>
+    {
+        Level::new(desc, self.position, self.bound(i), self.bound(i + 1))
+    }
+}
+
+#[derive(Default)]
+struct Partition {
+    file: Option<SpillFile>,
+    // the records that haven't been written to the file yet
+    tail: Vec<u8>,
+    count: usize,
+    bytes: usize,
+}
+
+impl Partition {
+    // what its records take once read back, roughly
+    fn size<K, V>(&self) -> usize {
+        self.bytes.saturating_add(self.count.saturating_mul(mem::size_of::<(K, V)>()))
+    }
+
+    fn flush(&mut self, dir: &Path) -> io::Result<()> {
+        if self.tail.is_empty() {
+            return Ok(());
+        }
+        if self.file.is_none() {
+            self.file = Some(SpillFile::create(dir)?);
+        }
+        if let Some(ref mut file) = self.file {
+            file.file.write_all(&self.tail)?;
+        }
+        self.tail = Vec::new();
+        Ok(())
+    }
+}
+
+// a spill file, which is removed once it has been read back
+struct SpillFile {
+    path: PathBuf,
+    file: File,
+}
+
+impl SpillFile {
+    fn create(dir: &Path) -> io::Result<Self> {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let name = format!("discrimination-{}-{}.spill",
+                           process::id(),
+                           NEXT.fetch_add(1, Ordering::Relaxed));
+        let path = dir.join(name);
+        let file = OpenOptions::new().read(true).write(true).create_new(true).open(&path)?;
+        Ok(SpillFile { path,
+                       file, })
+    }
+}
+
+impl Drop for SpillFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+fn read<K: Spill, V: Spill, R: Read>(mut input: R, count: usize)
+                                     -> impl Iterator<Item = io::Result<(K, V)>>
+{
+    (0..count).map(move |_| {
+                       let k = K::unspill(&mut input)?;
+                       Ok((k, V::unspill(&mut input)?))
+                   })
+}
+
+fn discriminate<'a, K, V, D>(desc: &'a D, records: Vec<(K, V)>, f: &mut dyn FnMut(Group<V>))
+                             -> io::Result<()>
+    where K: 'a,
+          V: 'a,
+          D: ?Sized + Discriminator<'a, K>
+{
+    let groups = desc.try_discriminate_sorted(records).map_err(invalid_input)?;
+    groups.for_each(f);
+    Ok(())
+}
@@ -0,0 +1,88 @@
+/// Defines a discriminator for a fieldless (C-like) enum, mapping each variant
+/// onto a `Natural` bucket.
+///
+/// ```text
+/// fieldless!(pub SuitDesc for Suit { Clubs, Diamonds, Hearts, Spades });
+/// ```
+///
+/// Variants are ordered by their discriminant values, as with a derived `Ord`,
+/// and the limit is the number of variants.  Every variant must be listed;
+/// adding a variant to the enum without adding it here is a compile error.
+/// The discriminator accepts both `Suit` and `&Suit` keys.
+#[macro_export]
+macro_rules! fieldless {
+    ($(#[$attr:meta])* $vis:vis $desc:ident for $name:ident { $($variant:ident),+ $(,)? }) => {
+        $(#[$attr])*
+        #[derive(Debug,Copy,Clone)]
+        $vis struct $desc {
+            by_value: $crate::discriminator::Map<fn($name) -> usize,
+                                                 $crate::discriminator::Natural>,
+            by_ref: $crate::discriminator::Map<for<'s> fn(&'s $name) -> usize,
+                                               $crate::discriminator::Natural>,
+        }
+
+        impl $desc {
+            pub fn new() -> Self {
+                const VALUES: &[i128] = &[$($name::$variant as i128),+];
+
+                // the number of variants with a smaller discriminant, which is
+                // always less than the number of variants
+                fn index(k: &$name) -> usize {
+                    match *k {
+                        $($name::$variant => {
+                            const RANK: usize = {
+                                let mut rank = 0;
+                                let mut i = 0;
+                                while i < VALUES.len() {
+                                    if VALUES[i] < $name::$variant as i128 {
+                                        rank += 1;
+                                    }
+                                    i += 1;
+                                }
+                                rank
+                            };
+                            RANK
+                        })+
+                    }
+                }
+                fn index_owned(k: $name) -> usize {
+                    index(&k)
+                }
+
+                let natural = $crate::discriminator::Natural::new(Ord::max(VALUES.len(), 2));
+                $desc { by_value: $crate::discriminator::Map(index_owned, natural),
+                        by_ref: $crate::discriminator::Map(index, natural), }
+            }
+        }
+
+        impl Default for $desc {
+            fn default() -> Self {
+                $desc::new()
+            }
+        }
+
+        impl<'a> $crate::discriminator::Discriminator<'a, $name> for $desc {
+            type Groups<V: 'a> = $crate::discriminator::Bucketed<V>;
+
+            fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> Self::Groups<V>
+                where I: IntoIterator,
+                      I::Item: Into<($name, V)>,
+                      I::IntoIter: DoubleEndedIterator + 'a
+            {
+                $crate::discriminator::Discriminator::discriminate_sorted(&self.by_value, pairs)
+            }
+        }
+
+        impl<'a, 's: 'a> $crate::discriminator::Discriminator<'a, &'s $name> for $desc {
+            type Groups<V: 'a> = $crate::discriminator::Bucketed<V>;
+
+            fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> Self::Groups<V>
+                where I: IntoIterator,
+                      I::Item: Into<(&'s $name, V)>,
+                      I::IntoIter: DoubleEndedIterator + 'a
+            {
+                $crate::discriminator::Discriminator::discriminate_sorted(&self.by_ref, pairs)
+            }
+        }
+    }
+}
@@ -0,0 +1,191 @@
+use alloc::rc::Rc;
+use alloc::sync::Arc;
+#[cfg(feature = "std")]
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use core::any::TypeId;
+use core::ptr::NonNull;
+use discriminator::{Discriminator, Usize};
+#[cfg(feature = "std")]
+use parking_lot::Mutex;
+#[cfg(feature = "std")]
+use std::collections::HashMap;
+#[cfg(feature = "std")]
+use std::sync::OnceLock;
+
+/// Keys that are identified by the address they point to.
+pub trait Pointer {
+    fn address(&self) -> usize;
+}
+
+impl<T: ?Sized> Pointer for *const T {
+    fn address(&self) -> usize {
+        self.cast::<()>() as usize
+    }
+}
+
+impl<T: ?Sized> Pointer for *mut T {
+    fn address(&self) -> usize {
+        self.cast::<()>() as usize
+    }
+}
+
+impl<T: ?Sized> Pointer for NonNull<T> {
+    fn address(&self) -> usize {
+        self.as_ptr().address()
+    }
+}
+
+impl<T: ?Sized> Pointer for &T {
+    fn address(&self) -> usize {
+        (*self as *const T).address()
+    }
+}
+
+impl<T: ?Sized> Pointer for Rc<T> {
+    fn address(&self) -> usize {
+        Rc::as_ptr(self).address()
+    }
+}
+
+impl<T: ?Sized> Pointer for Arc<T> {
+    fn address(&self) -> usize {
+        Arc::as_ptr(self).address()
+    }
+}
+
+/// Discriminates pointers by the address they point to, so that clones of
+/// the same `Rc` or references to the same object fall into the same group.
+///
+/// The order of the groups is arbitrary, but deterministic within a run.
+/// Pointers to zero-sized values may share an address without being the same
+/// object.
+#[derive(Debug,Copy,Clone,Default)]
+pub struct Address;
+
+impl Address {
+    pub fn new() -> Self {
+        Address
+    }
+}
+
+impl<'a, P: Pointer + 'a> Discriminator<'a, P> for Address {
+    type Groups<V: 'a> = <Usize as Discriminator<'a, usize>>::Groups<V>;
+
+    fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> Self::Groups<V>
+        where I: IntoIterator,
+              I::Item: Into<(P, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        const DESC: &Usize = &Usize;
+        DESC.discriminate_sorted(pairs.into_iter().map(|kv| {
+            let (k, v) = kv.into();
+            (k.address(), v)
+        }))
+    }
+}
+
+// `TypeId` exposes no integer representation, so each one is numbered the
+// first time it is seen.  The pairs are collected before taking the lock once
+// for all of them, so that their conversions never run while it is held.
+#[cfg(feature = "std")]
+fn type_indexed<K, V, I>(pairs: I, id: fn(K) -> TypeId) -> Vec<(usize, V)>
+    where I: IntoIterator,
+          I::Item: Into<(K, V)>
+{
+    static INDICES: OnceLock<Mutex<HashMap<TypeId, usize>>> = OnceLock::new();
+    let pairs: Vec<(K, V)> = pairs.into_iter().map(Into::into).collect();
+    let mut indices = INDICES.get_or_init(|| Mutex::new(HashMap::new())).lock();
+    pairs.into_iter()
+         .map(|(k, v)| {
+                  let next = indices.len();
+                  (*indices.entry(id(k)).or_insert(next), v)
+              })
+         .collect()
+}
+
+/// Discriminates `TypeId`s by identity.
+///
+/// The order of the groups is arbitrary, but deterministic within a run.
+/// Numbering types needs a global table, so this needs the `std` feature.
+/// Every type discriminated is numbered in that table for the rest of the
+/// process, which grows with the number of distinct types but never shrinks.
+#[cfg(feature = "std")]
+#[derive(Debug,Copy,Clone,Default)]
+pub struct TypeIdentity;
+
+#[cfg(feature = "std")]
+impl TypeIdentity {
+    pub fn new() -> Self {
+        TypeIdentity
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'a> Discriminator<'a, TypeId> for TypeIdentity {
+    type Groups<V: 'a> = <Usize as Discriminator<'a, usize>>::Groups<V>;
+
+    fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> Self::Groups<V>
+        where I: IntoIterator,
+              I::Item: Into<(TypeId, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        const DESC: &Usize = &Usize;
+        fn id(k: TypeId) -> TypeId {
+            k
+        }
+        DESC.discriminate_sorted(type_indexed(pairs, id))
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'a, 's: 'a> Discriminator<'a, &'s TypeId> for TypeIdentity {
+    type Groups<V: 'a> = <Usize as Discriminator<'a, usize>>::Groups<V>;
+
+    fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> Self::Groups<V>
+        where I: IntoIterator,
+              I::Item: Into<(&'s TypeId, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        const DESC: &Usize = &Usize;
+        fn id(k: &TypeId) -> TypeId {
+            *k
+        }
+        DESC.discriminate_sorted(type_indexed(pairs, id))
+    }
+}
+
+#[cfg(feature = "uuid")]
+pub use self::with_uuid::UuidBytes;
+
+#[cfg(feature = "uuid")]
+mod with_uuid {
+    use discriminator::{Discriminator, Map, U128};
+    use uuid::Uuid;
+
+    /// Discriminates UUIDs by their 16 bytes, most significant first.
+    #[derive(Debug,Copy,Clone,Default)]
+    pub struct UuidBytes;
+
+    impl UuidBytes {
+        pub fn new() -> Self {
+            UuidBytes
+        }
+    }
+
+    impl<'a> Discriminator<'a, Uuid> for UuidBytes {
+        type Groups<V: 'a> = <U128 as Discriminator<'a, u128>>::Groups<V>;
+
+        fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> Self::Groups<V>
+            where I: IntoIterator,
+                  I::Item: Into<(Uuid, V)>,
+                  I::IntoIter: DoubleEndedIterator + 'a
+        {
+            fn bytes(id: Uuid) -> u128 {
+                u128::from_be_bytes(*id.as_bytes())
+            }
+            const DESC: &Map<fn(Uuid) -> u128, U128> = &Map(bytes, U128);
+            DESC.discriminate_sorted(pairs)
+        }
+    }
+}
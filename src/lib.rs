@@ -5,23 +5,1208 @@
 //! [1]:http://www.diku.dk/hjemmesider/ansatte/henglein/papers/henglein2011a.pdf
 //! [2]:http://www.diku.dk/hjemmesider/ansatte/henglein/papers/henglein2011c.pdf
 //! [3]:https://github.com/ekmett/discrimination
+//!
+//! Everything but the `std` feature's keys and sharing modes needs only
+//! `alloc`, so the crate can be built with `default-features = false` for
+//! `no_std` targets.
+
+#![cfg_attr(not(feature = "std"), no_std)]
 
+#[macro_use]
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate core;
+#[cfg(all(test, not(feature = "std")))]
+extern crate std;
 pub extern crate either;
+#[cfg(feature = "chrono")]
+extern crate chrono;
+#[cfg(feature = "num-bigint")]
+extern crate num_bigint;
+#[cfg(feature = "parking_lot")]
 extern crate parking_lot;
+#[cfg(feature = "rayon")]
+extern crate rayon;
+#[cfg(feature = "rust_decimal")]
+extern crate rust_decimal;
+#[cfg(feature = "semver")]
+extern crate semver;
+#[cfg(feature = "time")]
+extern crate time;
+#[cfg(feature = "uuid")]
+extern crate uuid;
+
+#[macro_use]
+mod fieldless;
+
+pub mod builder;
+
+pub mod datetime;
 
 pub mod discriminator;
 
+pub mod error;
+
+#[cfg(feature = "std")]
+pub mod external;
+
+pub mod identity;
+
+pub mod net;
+
+#[cfg(any(feature = "num-bigint", feature = "rust_decimal"))]
+pub mod numeric;
+
+#[cfg(feature = "rayon")]
+pub mod parallel;
+
+#[cfg(feature = "std")]
+pub mod path;
+
+pub mod quantize;
+
+mod radix;
+
+pub mod sort;
+
 pub mod split_either;
 
+pub mod version;
+
 pub mod prelude {
     #[doc(no_inline)]
-    pub use discriminator::{Discriminator, Natural, Trivial, U16, U8};
+    pub use builder::{DiscriminatorBuilder, Incremental};
+    #[doc(no_inline)]
+    pub use discriminator::{Discriminator, I128, I16, I32, I64, I8, Isize, Natural, Prefix,
+                            Trivial, U128, U16, U32, U64, U8, Usize};
     #[doc(no_inline)]
     pub use either::Either;
+    #[cfg(feature = "rayon")]
+    #[doc(no_inline)]
+    pub use parallel::ParDiscriminator;
+    #[doc(no_inline)]
+    pub use sort::DiscSliceExt;
 }
 
 #[cfg(test)]
 mod tests {
+    use prelude::*;
+    #[cfg(not(feature = "std"))]
+    use std::prelude::v1::*;
+
+    // collects the groups of indices that tests compare across ways of
+    // discriminating the same pairs
+    fn groups<G: Iterator<Item = H>, H: Iterator<Item = usize>>(groups: G) -> Vec<Vec<usize>> {
+        groups.map(Iterator::collect).collect()
+    }
+
     #[test]
     fn it_works() {}
+
+    #[test]
+    fn largest_keys_have_buckets() {
+        let bytes = vec![(u8::MAX, 0), (0, 1), (u8::MAX, 2)];
+        let groups: Vec<Vec<usize>> = U8.discriminate_sorted(bytes)
+                                        .map(Iterator::collect)
+                                        .collect();
+        assert_eq!(groups, vec![vec![1], vec![0, 2]]);
+
+        let words = vec![(u16::MAX, 0), (0, 1), (u16::MAX, 2)];
+        let groups: Vec<Vec<usize>> = U16.discriminate_sorted(words)
+                                         .map(Iterator::collect)
+                                         .collect();
+        assert_eq!(groups, vec![vec![1], vec![0, 2]]);
+
+        let wide = vec![(u64::MAX, 0), (u64::from(u32::MAX), 1), (u64::MAX, 2)];
+        let groups: Vec<Vec<usize>> = U64.discriminate_sorted(wide)
+                                         .map(Iterator::collect)
+                                         .collect();
+        assert_eq!(groups, vec![vec![1], vec![0, 2]]);
+    }
+
+    #[test]
+    fn prefix_groups_by_leading_elements() {
+        let words = ["bcd", "ab", "abc", "b", "abd", "a", "ab"];
+        let disc = U8.prefix(2);
+        let groups: Vec<Vec<&str>> = disc.discriminate_sorted(words.iter()
+                                                                   .map(|w| (w.bytes(), *w)))
+                                         .map(Iterator::collect)
+                                         .collect();
+        assert_eq!(groups,
+                   vec![vec!["a"], vec!["ab", "abc", "abd", "ab"], vec!["b"], vec!["bcd"]]);
+
+        let disc = U8.lexicographic();
+        let sorted: Vec<&str> = disc.discriminate_sorted(words.iter().map(|w| (w.bytes(), *w)))
+                                    .flatten()
+                                    .collect();
+        assert_eq!(sorted, vec!["a", "ab", "ab", "abc", "abd", "b", "bcd"]);
+    }
+
+    #[test]
+    fn prefix_trie_refines_on_demand() {
+        let words = ["abc", "abd", "ab", "b"];
+        let disc = U8.prefix(1);
+        let mut trie = disc.trie(words.iter().map(|w| (w.bytes(), *w)));
+        assert_eq!(trie.children().iter().map(|child| child.0).collect::<Vec<_>>(),
+                   vec![b'a', b'b']);
+        assert!(trie.children()[0].1.is_pending());
+
+        trie.children_mut()[0].1.refine(&U8, 2);
+        {
+            let ab = &trie.children()[0].1.children()[0];
+            assert_eq!(ab.0, b'b');
+            assert_eq!(ab.1.terminal(), ["ab"]);
+            assert_eq!(ab.1.children().len(), 2);
+        }
+        assert_eq!(trie.len(), 4);
+        assert_eq!(trie.clone().into_values(), vec!["ab", "abc", "abd", "b"]);
+
+        let (terminal, children, pending) = trie.into_parts();
+        assert!(terminal.is_empty() && pending.is_empty());
+        assert_eq!(children.into_iter().map(|child| child.1.len()).collect::<Vec<_>>(),
+                   vec![3, 1]);
+    }
+
+    #[test]
+    fn long_prefixes_dont_recurse() {
+        // deeper than a test thread's stack would allow, were each element a
+        // call
+        let key = vec![7u8; 200_000];
+        let pairs = || vec![(key.iter().cloned(), 0), (key.iter().cloned(), 1)];
+        let desc: Prefix<U8> = Prefix::unbounded(U8);
+
+        let groups: Vec<Vec<usize>> = desc.discriminate_sorted(pairs())
+                                          .map(Iterator::collect)
+                                          .collect();
+        assert_eq!(groups, vec![vec![0, 1]]);
+
+        let trie = desc.trie(pairs());
+        assert_eq!(trie.len(), 2);
+        assert_eq!(trie.into_values(), vec![0, 1]);
+        drop(desc.trie(pairs()));
+    }
+
+    #[test]
+    fn semver_orders_by_precedence() {
+        use version::SemVer;
+
+        let versions = ["1.0.0", "1.0.0-alpha.beta", "1.0.0-rc.1", "not a version",
+                        "1.0.0-alpha", "1.0.0-beta.11", "1.0.0+build.5", "1.0.0-beta",
+                        "1.0.0-alpha.1", "0.9.65535", "1.0.0-beta.2", "2.0.0"];
+        let groups: Vec<Vec<&str>> = SemVer.discriminate_sorted(versions.iter().map(|v| (*v, *v)))
+                                           .map(Iterator::collect)
+                                           .collect();
+        assert_eq!(groups,
+                   vec![vec!["0.9.65535"],
+                        vec!["1.0.0-alpha"],
+                        vec!["1.0.0-alpha.1"],
+                        vec!["1.0.0-alpha.beta"],
+                        vec!["1.0.0-beta"],
+                        vec!["1.0.0-beta.2"],
+                        vec!["1.0.0-beta.11"],
+                        vec!["1.0.0-rc.1"],
+                        vec!["1.0.0", "1.0.0+build.5"],
+                        vec!["2.0.0"],
+                        vec!["not a version"]]);
+    }
+
+    #[cfg(feature = "semver")]
+    #[test]
+    fn semver_versions_order_as_the_semver_crate() {
+        use semver::Version;
+        use version::SemVer;
+
+        let versions = ["1.0.0-alpha.beta", "1.0.0-18446744073709551616", "1.0.0-99",
+                        "1.0.0+build.5", "1.0.0-alpha.1", "1.0.0", "1.0.0-rc.1",
+                        "1.0.0-18446744073709551615", "0.9.65535", "1.0.0-alpha-1", "2.0.0"];
+        let parsed: Vec<Version> = versions.iter().map(|v| Version::parse(v).unwrap()).collect();
+        let mut expected: Vec<usize> = (0..parsed.len()).collect();
+        expected.sort_by(|&i, &j| parsed[i].cmp_precedence(&parsed[j]).then(i.cmp(&j)));
+
+        let by_str: Vec<usize> = SemVer.discriminate_sorted(versions.iter().cloned().zip(0..11))
+                                       .flatten()
+                                       .collect();
+        assert_eq!(by_str, expected);
+        let by_version: Vec<usize> = SemVer.discriminate_sorted(parsed.iter().zip(0..11))
+                                           .flatten()
+                                           .collect();
+        assert_eq!(by_version, expected);
+
+        // malformed build metadata, and numbers the `semver` crate can't hold
+        for bad in ["1.0.0+", "1.0.0+a..b", "1.0.0+a+b", "1.0.0+a!", "18446744073709551616.0.0"] {
+            assert!(Version::parse(bad).is_err());
+            let groups: Vec<Vec<&str>> = SemVer.discriminate_sorted(vec![(bad, bad),
+                                                                         ("9.9.9", "9.9.9")])
+                                               .map(Iterator::collect)
+                                               .collect();
+            assert_eq!(groups, vec![vec!["9.9.9"], vec![bad]]);
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn paths_order_by_component() {
+        use path::{PathComponents, by_directory};
+        use std::path::Path;
+
+        let paths = ["a.b", "a/b", "a-b", "a/b/c", "a//b/", "a"];
+        let groups: Vec<Vec<&str>> =
+            PathComponents.discriminate_sorted(paths.iter().map(|p| (Path::new(p), *p)))
+                          .map(Iterator::collect)
+                          .collect();
+        assert_eq!(groups,
+                   vec![vec!["a"], vec!["a/b", "a//b/"], vec!["a/b/c"], vec!["a-b"], vec!["a.b"]]);
+
+        let dirs = by_directory(paths.iter().map(|p| (Path::new(p), *p)));
+        assert_eq!(dirs,
+                   vec![(Path::new(""), vec!["a.b", "a-b", "a"]),
+                        (Path::new("a"), vec!["a/b", "a//b/"]),
+                        (Path::new("a/b"), vec!["a/b/c"])]);
+    }
+
+    #[test]
+    fn ip_addresses_group_by_prefix() {
+        use net::{Ip, Ipv6, by_ipv4_prefix};
+        use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+        let addrs: Vec<Ipv4Addr> = ["10.0.1.7", "192.168.0.1", "10.0.0.255", "10.0.1.1"]
+            .iter()
+            .map(|a| a.parse().unwrap())
+            .collect();
+        let nets = by_ipv4_prefix(24, addrs.iter().map(|a| (*a, a.to_string())));
+        assert_eq!(nets,
+                   vec![("10.0.0.0".parse().unwrap(), vec!["10.0.0.255".to_string()]),
+                        ("10.0.1.0".parse().unwrap(),
+                         vec!["10.0.1.7".to_string(), "10.0.1.1".to_string()]),
+                        ("192.168.0.0".parse().unwrap(), vec!["192.168.0.1".to_string()])]);
+
+        let ips: Vec<IpAddr> = ["::ffff:10.0.0.1", "10.0.0.1", "::1"].iter()
+                                                                      .map(|a| a.parse().unwrap())
+                                                                      .collect();
+        let groups: Vec<Vec<usize>> = Ip::v4_first().discriminate_sorted(ips.iter()
+                                                                            .cloned()
+                                                                            .zip(0..ips.len()))
+                                                    .map(Iterator::collect)
+                                                    .collect();
+        assert_eq!(groups, vec![vec![1], vec![2], vec![0]]);
+        let groups: Vec<Vec<usize>> = Ip::v4_mapped().discriminate_sorted(ips.iter()
+                                                                             .cloned()
+                                                                             .zip(0..ips.len()))
+                                                     .map(Iterator::collect)
+                                                     .collect();
+        assert_eq!(groups, vec![vec![2], vec![0, 1]]);
+
+        // whole words group exactly as their octets would
+        let words: Vec<u128> = (0..200u128).map(|i| (i.pow(3) % 4099) << 100 | (i % 7)).collect();
+        let octets: Vec<[u8; 16]> = words.iter().map(|w| w.to_be_bytes()).collect();
+        let by_octets: Vec<Vec<usize>> =
+            U8.lexicographic()
+              .discriminate_sorted(octets.iter().map(|o| o.iter().cloned()).zip(0..200))
+              .map(Iterator::collect)
+              .collect();
+        let addrs = words.iter().map(|&w| Ipv6Addr::from(w));
+        let by_word: Vec<Vec<usize>> = Ipv6.discriminate_sorted(addrs.zip(0..200))
+                                           .map(Iterator::collect)
+                                           .collect();
+        assert_eq!(by_word, by_octets);
+    }
+
+    #[test]
+    fn socket_addresses_order_by_address_then_port() {
+        use net::{Socket, SocketV4, SocketV6};
+        use std::net::{SocketAddr, SocketAddrV4, SocketAddrV6};
+
+        let addrs: Vec<SocketAddr> = ["[::1]:80", "10.0.0.2:80", "10.0.0.1:443", "[::1]:22",
+                                      "10.0.0.1:80", "[::]:8080", "10.0.0.1:443"]
+            .iter()
+            .map(|a| a.parse().unwrap())
+            .collect();
+        let groups: Vec<Vec<usize>> = Socket.discriminate_sorted(addrs.iter().cloned().zip(0..7))
+                                            .map(Iterator::collect)
+                                            .collect();
+        assert_eq!(groups, vec![vec![4], vec![2, 6], vec![1], vec![5], vec![3], vec![0]]);
+
+        let v4: Vec<SocketAddrV4> = ["10.0.0.1:443", "9.255.255.255:65535", "10.0.0.1:80"]
+            .iter()
+            .map(|a| a.parse().unwrap())
+            .collect();
+        let groups: Vec<Vec<usize>> = SocketV4.discriminate_sorted(v4.iter().cloned().zip(0..3))
+                                              .map(Iterator::collect)
+                                              .collect();
+        assert_eq!(groups, vec![vec![1], vec![2], vec![0]]);
+
+        // flow information and scope ID break ties after the port
+        let ip = "fe80::1".parse().unwrap();
+        let v6 = [SocketAddrV6::new(ip, 80, 0, 2),
+                  SocketAddrV6::new(ip, 80, 1, 0),
+                  SocketAddrV6::new(ip, 80, 0, 2),
+                  SocketAddrV6::new(ip, 79, 9, 9),
+                  SocketAddrV6::new(ip, 80, 0, 1)];
+        let groups: Vec<Vec<usize>> = SocketV6.discriminate_sorted(v6.iter().cloned().zip(0..5))
+                                              .map(Iterator::collect)
+                                              .collect();
+        assert_eq!(groups, vec![vec![3], vec![4], vec![0, 2], vec![1]]);
+        let mut sorted = v6.to_vec();
+        sorted.sort();
+        sorted.dedup();
+        assert_eq!(groups.iter().map(|g| v6[g[0]]).collect::<Vec<_>>(), sorted);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn signed_and_wrapped_keys() {
+        use discriminator::{NonZero, Reversed};
+        use std::num::NonZeroU16;
+        use datetime::Timestamp;
+        use std::cmp::Reverse;
+        use std::time::{Duration, UNIX_EPOCH};
+
+        let keys = [3i32, -1, i32::MIN, 0, -1, i32::MAX];
+        let sorted: Vec<i32> = I32.discriminate_sorted(keys.iter().map(|k| (*k, *k)))
+                                  .flatten()
+                                  .collect();
+        assert_eq!(sorted, vec![i32::MIN, -1, -1, 0, 3, i32::MAX]);
+
+        let sorted: Vec<i32> = Reversed(I32).discriminate_sorted(keys.iter()
+                                                                     .map(|k| (Reverse(*k), *k)))
+                                            .flatten()
+                                            .collect();
+        assert_eq!(sorted, vec![i32::MAX, 3, 0, -1, -1, i32::MIN]);
+
+        let keys = [NonZeroU16::new(9).unwrap(), NonZeroU16::MAX, NonZeroU16::MIN];
+        let sorted: Vec<u16> = NonZero(U16).discriminate_sorted(keys.iter().map(|k| (*k, k.get())))
+                                           .flatten()
+                                           .collect();
+        assert_eq!(sorted, vec![1, 9, u16::MAX]);
+
+        let times = [UNIX_EPOCH + Duration::new(5, 1),
+                     UNIX_EPOCH - Duration::new(1, 0),
+                     UNIX_EPOCH,
+                     UNIX_EPOCH - Duration::new(7, 500)];
+        let order: Vec<usize> = Timestamp.discriminate_sorted(times.iter()
+                                                                   .cloned()
+                                                                   .zip(0..times.len()))
+                                         .flatten()
+                                         .collect();
+        assert_eq!(order, vec![3, 1, 2, 0]);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn quantize_into_labelled_bins() {
+        use quantize::{Buckets, Quantize};
+        use std::time::{Duration, UNIX_EPOCH};
+
+        let bins = Quantize::new(0i64, 10).bins(vec![(25, 'a'), (-3, 'b'), (21, 'c'), (0, 'd')]);
+        assert_eq!(bins, vec![(-1, vec!['b']), (0, vec!['d']), (2, vec!['a', 'c'])]);
+
+        // 1970-01-05 was the first Monday after the epoch
+        let monday = UNIX_EPOCH + Duration::from_secs(4 * 86400);
+        let times = vec![(monday + Duration::from_secs(6 * 86400), 1),
+                         (monday - Duration::from_secs(1), 2),
+                         (monday, 3)];
+        assert_eq!(Quantize::iso_weeks().bins(times),
+                   vec![(-1, vec![2]), (0, vec![1, 3])]);
+
+        let buckets = Buckets::new(vec![1.0, 10.0, 100.0]);
+        assert_eq!(buckets.bins(vec![(50.0, 'a'), (0.5, 'b'), (10.0, 'c'), (1e6, 'd')]),
+                   vec![(0, vec!['b']), (2, vec!['a', 'c']), (3, vec!['d'])]);
+    }
+
+    #[test]
+    fn floats_bin_by_floor() {
+        use quantize::Quantize;
+
+        let desc = Quantize::new(0.0, 1.0);
+        let bins: Vec<i64> = [2.5, 0.0, -0.5, -1.0, -1e30, 1e30, f64::NAN].iter()
+                                                                        .map(|&x| desc.bin(x))
+                                                                        .collect();
+        assert_eq!(bins, vec![2, 0, -1, -1, i64::MIN, i64::MAX, i64::MAX]);
+    }
+
+    #[test]
+    fn quantize_rejects_widths_that_arent_positive() {
+        use core::time::Duration;
+        use quantize::Quantize;
+        use std::panic;
+
+        assert!(panic::catch_unwind(|| Quantize::new(0i64, 0)).is_err());
+        assert!(panic::catch_unwind(|| Quantize::new(0u64, 0)).is_err());
+        assert!(panic::catch_unwind(|| Quantize::new(Duration::ZERO, Duration::ZERO)).is_err());
+        for width in [0.0, -0.0, -1.0, f64::NAN] {
+            assert!(panic::catch_unwind(|| Quantize::new(0.0, width)).is_err());
+        }
+        assert_eq!(Quantize::new(0.0, f64::MIN_POSITIVE).bin(0.0), 0);
+    }
+
+    #[test]
+    fn natural_accepts_index_keys_directly() {
+        #[derive(Debug,Copy,Clone)]
+        struct Slot(u8);
+
+        impl From<Slot> for usize {
+            fn from(slot: Slot) -> usize {
+                slot.0 as usize
+            }
+        }
+
+        let desc = Natural::new(4usize);
+        let groups: Vec<Vec<char>> =
+            desc.discriminate_sorted(vec![(Slot(3), 'a'), (Slot(0), 'b'), (Slot(3), 'c')])
+                .map(|g| g.collect())
+                .collect();
+        assert_eq!(groups, vec![vec!['b'], vec!['a', 'c']]);
+
+        let groups: Vec<Vec<char>> = desc.discriminate_sorted(vec![(2u16, 'x'), (1, 'y')])
+                                         .map(|g| g.collect())
+                                         .collect();
+        assert_eq!(groups, vec![vec!['y'], vec!['x']]);
+    }
+
+    #[test]
+    fn bounded_keys_are_checked_on_construction() {
+        use discriminator::{Bounded, Dense};
+
+        assert!(Bounded::<3>::new(3).is_none());
+        let keys: Vec<Bounded<3>> = [2, 0, 2, 1].iter().filter_map(|&k| Bounded::new(k)).collect();
+        let groups: Vec<Vec<usize>> = Dense::<3>::new().discriminate_sorted(keys.into_iter()
+                                                                                .zip(0..4))
+                                                       .map(|g| g.collect())
+                                                       .collect();
+        assert_eq!(groups, vec![vec![1], vec![3], vec![0, 2]]);
+
+        // long inputs are counted and short ones bucketed, without checking
+        // the keys again, into the same groups `Natural` checks them into
+        let desc: Dense<1024> = Dense::new();
+        for &len in &[100u32, 5000] {
+            let keys = (0..len).map(|i| (i * 7919 % 1021) as usize);
+            let keys: Vec<Bounded<1024>> = keys.filter_map(Bounded::new).collect();
+            let dense: Vec<Vec<u32>> = desc.discriminate_sorted(keys.iter().cloned().zip(0..len))
+                                           .map(|g| g.collect())
+                                           .collect();
+            let natural: Vec<Vec<u32>> =
+                Natural::new(1024usize).discriminate_sorted(keys.iter()
+                                                                .map(|k| k.get())
+                                                                .zip(0..len))
+                                       .map(|g| g.collect())
+                                       .collect();
+            assert_eq!(dense, natural);
+        }
+    }
+
+    #[test]
+    fn try_discriminate_sorted_reports_bad_keys() {
+        use discriminator::{Product, Span, Sum};
+        use error::DiscriminationError;
+
+        let desc = Natural::new(3usize);
+        assert_eq!(desc.try_discriminate_sorted(vec![(1usize, 'a'), (7, 'b')]).err(),
+                   Some(DiscriminationError::OutOfRange { key: 7,
+                                                          limit: 3, }));
+        assert_eq!(desc.try_discriminate_sorted(vec![(2usize, 'a'), (0, 'b')])
+                       .map(|groups| groups.count()),
+                   Ok(2));
+        assert_eq!(desc.try_discriminate_sorted(vec![(7usize, 'a')]).err(),
+                   Some(DiscriminationError::OutOfRange { key: 7,
+                                                          limit: 3, }));
+        assert_eq!(desc.try_discriminate_sorted(vec![(2usize, 'a')]).map(|groups| groups.count()),
+                   Ok(1));
+
+        let sum: Sum<U8, Natural> = Sum::left_biased(U8, desc);
+        let keys = vec![(Either::Left(200u8), 'a'),
+                        (Either::Right(3usize), 'b'),
+                        (Either::Right(0), 'c')];
+        assert!(sum.try_discriminate_sorted(keys).is_err());
+
+        let product: Product<U8, Natural> = Product::left_biased(U8, desc);
+        let keys = vec![((1u8, 0usize), 'a'), ((2, 5), 'b'), ((2, 1), 'c')];
+        assert!(matches!(product.try_discriminate_sorted(keys),
+                         Err(DiscriminationError::OutOfRange { key: 5, .. })));
+
+        let span = Span::new(1900i32..2100);
+        assert!(matches!(span.try_discriminate_sorted(vec![(1950, 'a'), (2100, 'b')]),
+                         Err(DiscriminationError::OutOfSpan { key: 2100, .. })));
+        assert!(matches!(span.try_discriminate_sorted(vec![(1899, 'a')]),
+                         Err(DiscriminationError::OutOfSpan { key: 1899, .. })));
+        assert!(span.try_discriminate_sorted(vec![(1900, 'a')]).is_ok());
+    }
+
+    #[test]
+    fn products_refine_groups_as_theyre_reached() {
+        use discriminator::Product;
+        use std::collections::BTreeMap;
+        use std::panic::{self, AssertUnwindSafe};
+
+        let desc: Product<U8, Natural> = Product::left_biased(U8, Natural::new(3usize));
+        let keys = vec![((2u8, 5usize), 'a'), ((1, 2), 'b'), ((1, 0), 'c'), ((3, 1), 'd')];
+        let mut groups = desc.discriminate_sorted(keys);
+        assert_eq!(groups.next().map(Iterator::collect::<Vec<_>>), Some(vec!['c']));
+        assert_eq!(groups.next_back().map(Iterator::collect::<Vec<_>>), Some(vec!['d']));
+        assert_eq!(groups.next().map(Iterator::collect::<Vec<_>>), Some(vec!['b']));
+        // the bad key's group is only refined now
+        assert!(panic::catch_unwind(AssertUnwindSafe(|| groups.next())).is_err());
+
+        // taken from both ends at once
+        fn alternately<G, H>(mut groups: G) -> Vec<Vec<usize>>
+            where G: DoubleEndedIterator<Item = H>,
+                  H: Iterator<Item = usize>
+        {
+            let (mut front, mut back) = (Vec::new(), Vec::new());
+            while let Some(group) = groups.next() {
+                front.push(group.collect());
+                match groups.next_back() {
+                    Some(group) => back.push(group.collect()),
+                    None => break,
+                }
+            }
+            front.extend(back.into_iter().rev());
+            front
+        }
+
+        let keys: Vec<(u8, u16, u8)> = (0..3000u32).map(|i| ((i % 7) as u8,
+                                                             (i * 7919 % 301) as u16,
+                                                             (i * 31 % 5) as u8))
+                                                  .collect();
+        let mut expected = BTreeMap::new();
+        for (i, &k) in keys.iter().enumerate() {
+            expected.entry(k).or_insert_with(Vec::new).push(i);
+        }
+        let expected: Vec<Vec<usize>> = expected.into_values().collect();
+
+        let nested_left: Product<Product<U8, U16>, U8> =
+            Product::left_biased(Product::left_biased(U8, U16), U8);
+        let pairs = || keys.iter().map(|&(a, b, c)| ((a, b), c)).zip(0..keys.len());
+        assert_eq!(alternately(nested_left.discriminate_sorted(pairs())), expected);
+        assert_eq!(alternately(nested_left.try_discriminate_sorted(pairs()).unwrap()), expected);
+
+        let nested_right: Product<U8, Product<U16, U8>> =
+            Product::left_biased(U8, Product::left_biased(U16, U8));
+        let pairs = || keys.iter().map(|&(a, b, c)| (a, (b, c))).zip(0..keys.len());
+        assert_eq!(alternately(nested_right.discriminate_sorted(pairs())), expected);
+        assert_eq!(alternately(nested_right.try_discriminate_sorted(pairs()).unwrap()), expected);
+    }
+
+    #[test]
+    fn try_map_key_sets_rejects_aside() {
+        let desc = U8.try_map_key(|s: &str| s.parse::<u8>());
+        let rows = || vec![("7", 'a'), ("x", 'b'), ("3", 'c'), ("300", 'd'), ("7", 'e')];
+
+        let groups: Vec<Vec<char>> = desc.discriminate_sorted(rows())
+                                         .map(|g| g.collect())
+                                         .collect();
+        assert_eq!(groups, vec![vec!['c'], vec!['a', 'e'], vec!['b', 'd']]);
+
+        let (valid, rejects) = desc.discriminate_or_reject(rows());
+        assert_eq!(valid.map(|g| g.collect()).collect::<Vec<Vec<char>>>(),
+                   vec![vec!['c'], vec!['a', 'e']]);
+        assert_eq!(rejects.into_iter().map(|(_, v)| v).collect::<Vec<_>>(), vec!['b', 'd']);
+
+        assert!(desc.discriminate_or_fail(rows()).is_err());
+        assert!(desc.discriminate_or_fail(vec![("1", 'a'), ("2", 'b')]).is_ok());
+    }
+
+    #[test]
+    fn span_buckets_only_the_keys_range() {
+        use discriminator::Span;
+        use error::DiscriminationError;
+
+        let years = vec![(1999i32, 'a'), (1901, 'b'), (2050, 'c'), (1999, 'd')];
+        let expected = vec![vec!['b'], vec!['a', 'd'], vec!['c']];
+        for desc in &[Span::new(1900..2100), Span::auto()] {
+            let groups: Vec<Vec<char>> = desc.discriminate_sorted(years.clone())
+                                             .map(|g| g.collect())
+                                             .collect();
+            assert_eq!(groups, expected);
+        }
+
+        let dense = vec![(12u8, 0), (10, 1), (12, 2), (11, 3), (10, 4)];
+        let groups: Vec<Vec<usize>> = Span::auto().discriminate_sorted(dense)
+                                                  .map(|g| g.collect())
+                                                  .collect();
+        assert_eq!(groups, vec![vec![1, 4], vec![3], vec![0, 2]]);
+
+        let sparse = vec![(i64::MAX, 0), (-5, 1), (i64::MIN, 2), (-5, 3)];
+        let groups: Vec<Vec<usize>> = Span::auto().discriminate_sorted(sparse)
+                                                  .map(|g| g.collect())
+                                                  .collect();
+        assert_eq!(groups, vec![vec![2], vec![1, 3], vec![0]]);
+
+        // word-sized IDs, in a window and at the edges of their range
+        let ids = vec![(1_000_007usize, 0), (1_000_000, 1), (1_000_007, 2)];
+        let groups: Vec<Vec<usize>> = Span::new(1_000_000..1_000_100).discriminate_sorted(ids)
+                                                                      .map(|g| g.collect())
+                                                                      .collect();
+        assert_eq!(groups, vec![vec![1], vec![0, 2]]);
+        let ids = vec![(usize::MAX, 0), (0, 1), (isize::MAX as usize, 2)];
+        let groups: Vec<Vec<usize>> = Span::auto().discriminate_sorted(ids)
+                                                  .map(|g| g.collect())
+                                                  .collect();
+        assert_eq!(groups, vec![vec![1], vec![2], vec![0]]);
+        let offsets = vec![(isize::MIN, 0), (-1isize, 1), (isize::MAX, 2), (-1, 3)];
+        let groups: Vec<Vec<usize>> = Span::auto().discriminate_sorted(offsets)
+                                                  .map(|g| g.collect())
+                                                  .collect();
+        assert_eq!(groups, vec![vec![0], vec![1, 3], vec![2]]);
+
+        // 128-bit keys may be further apart than any offset of 64 bits
+        let wide = vec![(0i128, 0), (1 << 64, 1), (1, 2), ((1 << 64) + 1, 3)];
+        let groups: Vec<Vec<usize>> = Span::auto().discriminate_sorted(wide)
+                                                  .map(|g| g.collect())
+                                                  .collect();
+        assert_eq!(groups, vec![vec![0], vec![2], vec![1], vec![3]]);
+
+        let extremes = vec![(i128::MAX, 0), (i128::MIN, 1), (0, 2), (i128::MAX, 3)];
+        let groups: Vec<Vec<usize>> = Span::auto().discriminate_sorted(extremes.clone())
+                                                  .map(|g| g.collect())
+                                                  .collect();
+        assert_eq!(groups, vec![vec![1], vec![2], vec![0, 3]]);
+        assert_eq!(Span::new(i128::MIN..i128::MAX).try_discriminate_sorted(extremes).err(),
+                   Some(DiscriminationError::OutOfSpan { key: i128::MAX,
+                                                         start: i128::MIN,
+                                                         end: i128::MAX, }));
+        let groups: Vec<Vec<usize>> = Span::new(i128::MIN..i128::MAX)
+            .discriminate_sorted(vec![(i128::MAX - 1, 0), (i128::MIN, 1)])
+            .map(|g| g.collect())
+            .collect();
+        assert_eq!(groups, vec![vec![1], vec![0]]);
+    }
+
+    #[test]
+    fn nested_buckets_are_only_visited_when_touched() {
+        use discriminator::Product;
+
+        let desc: Product<U16, U16> = Product::left_biased(U16, U16);
+        let keys: Vec<(u16, u16)> = (0..20_000u32).map(|i| ((i * 7919 % 10_007) as u16,
+                                                            (i * 104_729 % 65_521) as u16))
+                                                 .collect();
+        let groups: Vec<Vec<(u16, u16)>> = desc.discriminate_sorted(keys.iter()
+                                                                        .map(|&k| (k, k)))
+                                               .map(Iterator::collect)
+                                               .collect();
+        let mut sorted = keys.clone();
+        sorted.sort();
+        sorted.dedup();
+        assert_eq!(groups.iter().map(|g| g[0]).collect::<Vec<_>>(), sorted);
+
+        // most of the buckets touched, so the table is swept rather than sorted
+        let keys: Vec<(u16, u16)> = (0..30_000u16).rev().map(|k| (k * 2, k)).collect();
+        let groups: Vec<Vec<u16>> = U16.discriminate_sorted(keys)
+                                       .map(Iterator::collect)
+                                       .collect();
+        assert_eq!(groups, (0..30_000u16).map(|k| vec![k]).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn long_inputs_are_counted_into_one_buffer() {
+        use std::rc::Rc;
+
+        let keys: Vec<u8> = (0..1000u32).map(|i| (i * 37 % 256) as u8).collect();
+        let groups: Vec<Vec<usize>> = U8.discriminate_sorted(keys.iter().cloned().zip(0..1000))
+                                        .map(Iterator::collect)
+                                        .collect();
+        assert_eq!(groups.len(), 256);
+        for (k, group) in groups.iter().enumerate() {
+            assert!(group.iter().all(|&i| keys[i] as usize == k));
+            assert!(group.windows(2).all(|w| w[0] < w[1]));
+        }
+
+        // values left in groups that are dropped part way through are dropped
+        // with them
+        let shared = Rc::new(());
+        {
+            let mut groups = U8.discriminate_sorted(keys.iter().map(|&k| (k, shared.clone())));
+            let mut first = groups.next().unwrap();
+            assert_eq!(first.as_slice().len(), 4);
+            first.next_back();
+            assert_eq!(first.len(), 3);
+            groups.next_back();
+        }
+        assert_eq!(Rc::strong_count(&shared), 1);
+    }
+
+    #[test]
+    fn shared_groups_drop_only_what_they_hold() {
+        use std::thread;
+
+        // counted into one buffer
+        let desc = Natural::new(4usize);
+        let pairs = || (0..16usize).map(|i| (i % 4, i.to_string()));
+        drop(desc.discriminate_sorted(pairs()));
+
+        let mut groups = desc.discriminate_sorted(pairs());
+        let mut first = groups.next().unwrap();
+        assert_eq!(first.next().as_deref(), Some("0"));
+        assert_eq!(first.next_back().as_deref(), Some("12"));
+        assert_eq!(first.as_slice(), ["4", "8"]);
+        let mut second = groups.next().unwrap();
+        second.next();
+        let rest = thread::spawn(move || groups.map(Iterator::collect).collect::<Vec<Vec<_>>>());
+        let rest = rest.join().unwrap();
+        assert_eq!(rest, [["2", "6", "10", "14"], ["3", "7", "11", "15"]]);
+        drop(first);
+        assert_eq!(second.collect::<Vec<_>>(), ["5", "9", "13"]);
+
+        // sorted by radix
+        let keys: Vec<u64> = (0..300u64).map(|i| (i * 7919 % 5) << 40).collect();
+        let mut groups = U64.discriminate_sorted(keys.iter().map(|&k| (k, k.to_string())));
+        let mut second = groups.nth(1).unwrap();
+        assert_eq!(second.next_back(), Some((1u64 << 40).to_string()));
+        assert_eq!(second.len(), 59);
+        drop(groups);
+        assert_eq!(thread::spawn(move || second.count()).join().unwrap(), 59);
+    }
+
+    #[test]
+    fn contexts_are_reused_across_discriminations() {
+        use discriminator::DiscriminationContext;
+        use std::panic::{self, AssertUnwindSafe};
+
+        let mut cx = DiscriminationContext::new();
+        let desc = U16.product_left(U8.map_key(|c: char| c as u8));
+        for round in 0..3u16 {
+            let keys = vec![((round, 'b'), 0), ((0, 'a'), 1), ((round, 'b'), 2), ((7, 'a'), 3)];
+            let groups: Vec<Vec<usize>> = desc.discriminate_sorted_in(&mut cx, keys)
+                                              .map(Iterator::collect)
+                                              .collect();
+            assert_eq!(groups, vec![vec![1], vec![0, 2], vec![3]]);
+        }
+
+        // a discrimination that panics part way through leaves nothing behind
+        let desc = Natural::new(100usize).map_key(|k: usize| {
+                                                      assert!(k != 99);
+                                                      k
+                                                  });
+        let res = panic::catch_unwind(AssertUnwindSafe(|| {
+            desc.discriminate_sorted_in(&mut cx, vec![(5, 'a'), (9, 'b'), (99, 'c')]).count()
+        }));
+        assert!(res.is_err());
+        let groups: Vec<Vec<char>> = desc.discriminate_sorted_in(&mut cx,
+                                                                 vec![(9, 'x'), (5, 'y')])
+                                         .map(Iterator::collect)
+                                         .collect();
+        assert_eq!(groups, vec![vec!['y'], vec!['x']]);
+    }
+
+    #[test]
+    fn slices_sort_in_place_by_key() {
+        use discriminator::{Invert, Product};
+
+        let records: Vec<(i32, u8, usize)> =
+            (0..500).map(|i| ((i * 7919 % 263) as i32 - 131, (i * 31 % 5) as u8, i)).collect();
+
+        let mut stable = records.clone();
+        stable.disc_sort_by_key(&I32, |r| r.0);
+        let mut expected = records.clone();
+        expected.sort_by_key(|r| r.0);
+        assert_eq!(stable, expected);
+
+        let desc: Product<Invert<U8>, I32> = Product::left_biased(Invert(U8), I32);
+        let mut unstable = records.clone();
+        unstable.disc_sort_unstable_by_key(&desc, |r| (r.1, r.0));
+        expected.sort_by_key(|r| (std::cmp::Reverse(r.1), r.0));
+        assert_eq!(unstable.iter().map(|r| (r.1, r.0)).collect::<Vec<_>>(),
+                   expected.iter().map(|r| (r.1, r.0)).collect::<Vec<_>>());
+
+        let mut small = vec![300usize, 2, 65_000, 2, 7];
+        small.disc_sort_unstable_by_key(&Natural::new(65_536usize), |&k| k);
+        assert_eq!(small, vec![2, 2, 7, 300, 65_000]);
+
+        // groups far smaller than the radix of their next digit are compared
+        let desc: Product<Natural, Natural> = Product::left_biased(Natural::new(65_536usize),
+                                                                   Natural::new(65_536usize));
+        let mut sparse: Vec<(usize, usize)> =
+            (0..2000).map(|i| (i * 7919 % 20, i * 7919 % 65_521)).collect();
+        let mut expected = sparse.clone();
+        sparse.disc_sort_unstable_by_key(&desc, |&k| k);
+        expected.sort_unstable();
+        assert_eq!(sparse, expected);
+    }
+
+    #[test]
+    fn integer_keys_sort_by_radix() {
+        use discriminator::Product;
+
+        // the top bytes are all the same, so their passes are skipped
+        let keys: Vec<u64> = (0..2000u64).map(|i| i * 2_654_435_761 % 100_003).collect();
+        let groups: Vec<Vec<usize>> = U64.discriminate_sorted(keys.iter().cloned().zip(0..2000))
+                                         .map(Iterator::collect)
+                                         .collect();
+        let mut expected: Vec<usize> = (0..2000).collect();
+        expected.sort_by_key(|&i| keys[i]);
+        assert_eq!(groups.concat(), expected);
+        assert!(groups.iter().all(|g| g.iter().all(|&i| keys[i] == keys[g[0]])));
+
+        let mut signed: Vec<i64> = keys.iter().map(|&k| 50_000 - k as i64).collect();
+        let mut expected = signed.clone();
+        signed.disc_sort_unstable_by_key(&I64, |&k| k);
+        expected.sort_unstable();
+        assert_eq!(signed, expected);
+
+        // long enough to be split by the leading digit before the rest
+        let len = if cfg!(miri) { 400 } else { 300_000 };
+        let mut long: Vec<u64> = (0..len).map(|i: u64| i.wrapping_mul(0x9e37_79b9_7f4a_7c15))
+                                         .collect();
+        let mut expected = long.clone();
+        long.disc_sort_unstable_by_key(&U64, |&k| k);
+        expected.sort_unstable();
+        assert_eq!(long, expected);
+
+        // elements caught in the buffer by a panicking key are moved back
+        let mut names: Vec<String> = keys[..100].iter().map(|k| k.to_string()).collect();
+        let mut calls = 0;
+        let sorting = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            names.disc_sort_unstable_by_key(&U64, |name| {
+                calls += 1;
+                assert!(calls < 250);
+                name.parse().unwrap()
+            })
+        }));
+        assert!(sorting.is_err());
+        names.sort();
+        let mut expected: Vec<String> = keys[..100].iter().map(|k| k.to_string()).collect();
+        expected.sort();
+        assert_eq!(names, expected);
+
+        // too many digits for a pass per digit, so sorted from the top instead
+        let desc: Product<U128, U128> = Product::right_biased(U128, U128);
+        let mut wide: Vec<(u128, u128)> = keys.iter().map(|&k| (k as u128, !k as u128)).collect();
+        let mut expected = wide.clone();
+        wide.disc_sort_unstable_by_key(&desc, |&k| k);
+        expected.sort_unstable_by_key(|k| (k.1, k.0));
+        assert_eq!(wide, expected);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn external_discrimination_spills_to_files() {
+        use discriminator::Product;
+        use external::External;
+        use std::{env, fs, io, process};
+
+        let dir = env::temp_dir().join(format!("discrimination-test-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let records: Vec<(u32, String)> = (0..3000u32).map(|i| (i * 7919 % 1009, i.to_string()))
+                                                      .collect();
+        let mut groups = Vec::new();
+        External::new(1000, &dir).discriminate(&U32, records.clone(), |g| {
+                                      groups.push(g.collect::<Vec<_>>())
+                                  })
+                                 .unwrap();
+        let expected: Vec<Vec<String>> = U32.discriminate_sorted(records.clone())
+                                            .map(Iterator::collect)
+                                            .collect();
+        assert_eq!(groups, expected);
+
+        // every key shares its leading bytes, and some groups are over budget
+        let desc: Product<U8, I16> = Product::right_biased(U8, I16);
+        let pairs = || records.iter().map(|(k, v)| (((k % 3) as u8, -(*k as i16 % 5)), v.clone()));
+        let mut groups: Vec<Vec<String>> = Vec::new();
+        External::new(500, &dir).discriminate(&desc, pairs(), |g| groups.push(g.collect()))
+                                .unwrap();
+        let expected: Vec<Vec<String>> = desc.discriminate_sorted(pairs())
+                                             .map(Iterator::collect)
+                                             .collect();
+        assert_eq!(groups, expected);
+
+        // digits too wide to split by one at a time are split by ranges, and
+        // ranges still over budget by narrower ones
+        let desc = Natural::new(100_000usize);
+        let pairs = || records.iter().map(|(k, v)| (*k as usize * 97, v.clone()));
+        let mut groups: Vec<Vec<String>> = Vec::new();
+        External::new(500, &dir).discriminate(&desc, pairs(), |g| groups.push(g.collect()))
+                                .unwrap();
+        let expected: Vec<Vec<String>> = desc.discriminate_sorted(pairs())
+                                             .map(Iterator::collect)
+                                             .collect();
+        assert_eq!(groups, expected);
+
+        // keys past the limit are reported rather than panicking
+        let desc: Product<U8, Natural> = Product::left_biased(U8, Natural::new(1000usize));
+        let pairs = records.iter().map(|(k, v)| (((k % 3) as u8, *k as usize), v.clone()));
+        let err = External::new(500, &dir).discriminate(&desc, pairs, |_| ()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+        fs::remove_dir(&dir).unwrap();
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn pointers_and_types_group_by_identity() {
+        use identity::{Address, TypeIdentity};
+        use std::any::TypeId;
+        use std::rc::Rc;
+
+        let (a, b) = (Rc::new(0), Rc::new(0));
+        let rcs = vec![a.clone(), b.clone(), a, b.clone(), b];
+        let mut groups: Vec<Vec<usize>> = Address.discriminate_sorted(rcs.into_iter().zip(0..5))
+                                                 .map(Iterator::collect)
+                                                 .collect();
+        groups.sort();
+        assert_eq!(groups, vec![vec![0, 2], vec![1, 3, 4]]);
+
+        let ids = [TypeId::of::<u8>(), TypeId::of::<str>(), TypeId::of::<u8>()];
+        let mut groups: Vec<Vec<usize>> = TypeIdentity.discriminate_sorted(ids.iter().zip(0..3))
+                                                      .map(|g| g.collect())
+                                                      .collect();
+        let owned: Vec<Vec<usize>> = TypeIdentity.discriminate_sorted(ids.iter().cloned().zip(0..3))
+                                                 .map(|g| g.collect())
+                                                 .collect();
+        assert_eq!(owned, groups);
+        groups.sort();
+        assert_eq!(groups, vec![vec![0, 2], vec![1]]);
+    }
+
+    #[derive(Debug,Copy,Clone,PartialEq,Eq)]
+    enum Level {
+        Warn = 4,
+        Debug = 1,
+        Error = 8,
+        Info = 2,
+    }
+
+    fieldless!(LevelDesc for Level { Warn, Debug, Error, Info });
+
+    #[test]
+    fn fieldless_enums_order_by_discriminant() {
+        use self::Level::*;
+
+        let levels = [Error, Info, Debug, Error, Warn];
+        let groups: Vec<Vec<usize>> = LevelDesc::new().discriminate_sorted(levels.iter().zip(0..5))
+                                                      .map(|g| g.collect())
+                                                      .collect();
+        assert_eq!(groups, vec![vec![2], vec![1], vec![4], vec![0, 3]]);
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn chrono_keys_group_by_calendar_unit() {
+        use chrono::{DateTime, FixedOffset, NaiveDate};
+        use datetime::{Calendar, CalendarUnit, Chronological};
+
+        let times: Vec<DateTime<FixedOffset>> = ["2024-03-04T23:30:00-02:00",
+                                                 "2024-03-05T00:15:00+00:00",
+                                                 "2024-03-03T12:00:00+00:00",
+                                                 "2024-03-05T02:00:00+01:00"]
+            .iter()
+            .map(|t| DateTime::parse_from_rfc3339(t).unwrap())
+            .collect();
+        let order: Vec<usize> = Chronological.discriminate_sorted(times.iter()
+                                                                       .cloned()
+                                                                       .zip(0..times.len()))
+                                             .flatten()
+                                             .collect();
+        assert_eq!(order, vec![2, 1, 3, 0]);
+
+        let days: Vec<(NaiveDate, Vec<usize>)> =
+            Calendar::new(CalendarUnit::Day).groups(times.iter().cloned().zip(0..times.len()))
+                                            .into_iter()
+                                            .map(|(day, vs)| (day.naive_utc().date(), vs))
+                                            .collect();
+        assert_eq!(days,
+                   vec![(NaiveDate::from_ymd_opt(2024, 3, 3).unwrap(), vec![2]),
+                        (NaiveDate::from_ymd_opt(2024, 3, 5).unwrap(), vec![0, 1, 3])]);
+
+        let weeks = Calendar::new(CalendarUnit::IsoWeek).groups(times.into_iter().zip(0..4));
+        assert_eq!(weeks.iter().map(|w| w.0).collect::<Vec<_>>(),
+                   vec![DateTime::parse_from_rfc3339("2024-02-26T00:00:00+00:00").unwrap(),
+                        DateTime::parse_from_rfc3339("2024-03-03T22:00:00-02:00").unwrap()]);
+    }
+
+    #[cfg(feature = "time")]
+    #[test]
+    fn time_keys_group_by_calendar_unit() {
+        use datetime::{Calendar, CalendarTime, CalendarUnit, Chronological};
+        use time::{Date, Duration, Month, OffsetDateTime, PrimitiveDateTime, UtcOffset, Weekday};
+
+        let at = |(month, day): (Month, u8), (h, m): (u8, u8), offset: i8| -> OffsetDateTime {
+            let date = Date::from_calendar_date(2024, month, day).unwrap();
+            let offset = UtcOffset::from_hms(offset, 0, 0).unwrap();
+            date.with_hms(h, m, 0).unwrap().assume_offset(offset)
+        };
+        let march = |day| (Month::March, day);
+        let times = vec![at(march(4), (23, 30), -2),
+                         at(march(5), (0, 15), 0),
+                         at(march(3), (12, 0), 0),
+                         at(march(5), (2, 0), 1)];
+        let order: Vec<usize> = Chronological.discriminate_sorted(times.iter()
+                                                                       .cloned()
+                                                                       .zip(0..times.len()))
+                                             .flatten()
+                                             .collect();
+        assert_eq!(order, vec![2, 1, 3, 0]);
+        let naive = times.iter().map(|t| {
+                                     let utc = t.to_offset(UtcOffset::UTC);
+                                     PrimitiveDateTime::new(utc.date(), utc.time())
+                                 });
+        let naive: Vec<usize> = Chronological.discriminate_sorted(naive.zip(0..times.len()))
+                                             .flatten()
+                                             .collect();
+        assert_eq!(naive, order);
+
+        let days: Vec<(Date, Vec<usize>)> =
+            Calendar::new(CalendarUnit::Day).groups(times.iter().cloned().zip(0..times.len()))
+                                            .into_iter()
+                                            .map(|(day, vs)| {
+                                                     (day.to_offset(UtcOffset::UTC).date(), vs)
+                                                 })
+                                            .collect();
+        assert_eq!(days,
+                   vec![(Date::from_calendar_date(2024, Month::March, 3).unwrap(), vec![2]),
+                        (Date::from_calendar_date(2024, Month::March, 5).unwrap(),
+                         vec![0, 1, 3])]);
+
+        let weeks = Calendar::new(CalendarUnit::IsoWeek).groups(times.into_iter().zip(0..4));
+        assert_eq!(weeks.iter().map(|w| w.0).collect::<Vec<_>>(),
+                   vec![at((Month::February, 26), (0, 0), 0), at(march(3), (22, 0), -2)]);
+
+        // weeks start on Mondays across a leap day and a year boundary
+        let mut date = Date::from_calendar_date(2023, Month::December, 20).unwrap();
+        let end = Date::from_calendar_date(2024, Month::March, 10).unwrap();
+        while date < end {
+            let monday = date.truncate(CalendarUnit::IsoWeek);
+            assert_eq!(monday.weekday(), Weekday::Monday);
+            assert!(date - monday < Duration::days(7));
+            assert_eq!(date.period(CalendarUnit::IsoWeek),
+                       monday.period(CalendarUnit::Day) / 7);
+            let next = date.next_day().unwrap();
+            assert_eq!(next.period(CalendarUnit::Day), date.period(CalendarUnit::Day) + 1);
+            assert_eq!(next.period(CalendarUnit::IsoWeek) > date.period(CalendarUnit::IsoWeek),
+                       next.weekday() == Weekday::Monday);
+            assert_eq!(next.period(CalendarUnit::Month) > date.period(CalendarUnit::Month),
+                       next.day() == 1);
+            date = next;
+        }
+    }
+
+    #[cfg(all(feature = "num-bigint", feature = "rust_decimal"))]
+    #[test]
+    fn big_and_decimal_numbers_group_by_value() {
+        use num_bigint::BigInt;
+        use numeric::{BigInteger, DecimalValue};
+        use rust_decimal::Decimal;
+
+        let ints: Vec<BigInt> = ["-100000000000000000000", "7", "0", "-3", "18446744073709551616"]
+            .iter()
+            .map(|n| n.parse().unwrap())
+            .collect();
+        let order: Vec<usize> = BigInteger.discriminate_sorted(ints.iter().zip(0..5))
+                                          .flatten()
+                                          .collect();
+        assert_eq!(order, vec![0, 3, 2, 1, 4]);
+
+        let decimals: Vec<Decimal> = ["1.00", "-0.5", "1.0", "0.25", "-12", "0.0"]
+            .iter()
+            .map(|d| d.parse().unwrap())
+            .collect();
+        let groups: Vec<Vec<usize>> =
+            DecimalValue.discriminate_sorted(decimals.into_iter().zip(0..6))
+                        .map(|g| g.collect())
+                        .collect();
+        assert_eq!(groups, vec![vec![4], vec![1], vec![5], vec![3], vec![0, 2]]);
+    }
+
+    #[cfg(feature = "uuid")]
+    #[test]
+    fn uuids_order_by_bytes() {
+        use identity::UuidBytes;
+        use uuid::Uuid;
+
+        let ids = [Uuid::from_u128(2), Uuid::from_u128(1 << 100), Uuid::nil(), Uuid::from_u128(2)];
+        let groups: Vec<Vec<usize>> = UuidBytes.discriminate_sorted(ids.iter().cloned().zip(0..4))
+                                               .map(Iterator::collect)
+                                               .collect();
+        assert_eq!(groups, vec![vec![2], vec![0, 3], vec![1]]);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn parallel_discrimination_matches_sequential() {
+        use discriminator::{Invert, Product, Sum};
+        use either::Either::{Left, Right};
+        use error::DiscriminationError;
+
+        let n = 100_000;
+        let keys: Vec<u64> = (0..n as u64).map(|i| i * 2_654_435_761 % 10_007).collect();
+        let pairs = || keys.iter().cloned().zip(0..n);
+
+        let desc = Natural::new(10_007usize);
+        let small = || pairs().map(|(k, i)| (k as usize, i));
+        assert_eq!(groups(desc.par_discriminate_sorted(small().collect())),
+                   groups(desc.discriminate_sorted(small())));
+        assert_eq!(groups(U64.par_discriminate_sorted(pairs().collect())),
+                   groups(U64.discriminate_sorted(pairs())));
+        let signed = || pairs().map(|(k, i)| (5_000 - k as i32, i));
+        assert_eq!(groups(I32.par_discriminate_sorted(signed().collect())),
+                   groups(I32.discriminate_sorted(signed())));
+
+        let desc: Product<U8, Invert<U16>> = Product::left_biased(U8, Invert(U16));
+        let split = || pairs().map(|(k, i)| (((k % 7) as u8, k as u16), i));
+        assert_eq!(groups(desc.par_discriminate_sorted(split().collect())),
+                   groups(desc.discriminate_sorted(split())));
+
+        let desc: Sum<U32, Natural> = Sum::right_biased(U32, Natural::new(10_007usize));
+        let either = || {
+            pairs().map(|(k, i)| (if i % 3 == 0 { Left(k as u32) } else { Right(k as usize) }, i))
+        };
+        assert_eq!(groups(desc.par_discriminate_sorted(either().collect())),
+                   groups(desc.discriminate_sorted(either())));
+
+        let desc = Natural::new(10_000usize);
+        assert_eq!(desc.try_par_discriminate_sorted(small().collect()).err(),
+                   desc.try_discriminate_sorted(small()).err());
+        assert!(matches!(desc.try_par_discriminate_sorted(small().collect()),
+                         Err(DiscriminationError::OutOfRange { .. })));
+    }
+
+    #[test]
+    fn builders_take_pairs_as_they_arrive() {
+        use discriminator::Span;
+        use either::Either::{Left, Right};
+        use error::DiscriminationError;
+
+        let keys = [5usize, 1, 9, 1, 0, 5, 5, 3];
+        let pairs = || keys.iter().cloned().zip(0..keys.len());
+
+        let desc = Natural::new(10usize);
+        let mut builder = DiscriminatorBuilder::new(&desc);
+        builder.push(5usize, 0);
+        builder.extend(pairs().skip(1).take(3));
+        builder.extend(pairs().skip(4));
+        assert_eq!(groups(builder.finish()), groups(desc.discriminate_sorted(pairs())));
+
+        let wide = || pairs().map(|(k, i)| (k as u64 * 1_000_003, i));
+        let mut builder = DiscriminatorBuilder::new(&U64);
+        for batch in wide().collect::<Vec<_>>().chunks(3) {
+            builder.extend(batch.iter().cloned());
+        }
+        assert_eq!(groups(builder.finish()), groups(U64.discriminate_sorted(wide())));
+
+        let desc = U8.sum_right(U8.product_left(U32).map_key(|k: u32| ((k % 2) as u8, k)));
+        let either = || {
+            pairs().map(|(k, i)| (if i % 3 == 0 { Left(k as u8) } else { Right(k as u32) }, i))
+        };
+        let mut builder = DiscriminatorBuilder::new(&desc);
+        builder.extend(either());
+        assert_eq!(groups(builder.finish()), groups(desc.discriminate_sorted(either())));
+
+        let desc = Natural::new(5usize);
+        let mut builder = DiscriminatorBuilder::new(&desc);
+        assert_eq!(builder.try_extend(pairs()),
+                   Err(DiscriminationError::OutOfRange { key: 5, limit: 5 }));
+        builder.try_push(4usize, 10).unwrap();
+        assert_eq!(groups(builder.try_finish().unwrap()), vec![vec![10]]);
+
+        let desc = Span::new(-5i32..5);
+        let mut builder = DiscriminatorBuilder::new(&desc);
+        builder.try_push(-5, 0).unwrap();
+        assert_eq!(builder.try_push(5, 1),
+                   Err(DiscriminationError::OutOfSpan { key: 5,
+                                                        start: -5,
+                                                        end: 5, }));
+        assert!(builder.try_push(-6, 2).is_err());
+        builder.try_push(4, 3).unwrap();
+        assert_eq!(groups(builder.try_finish().unwrap()), vec![vec![0], vec![3]]);
+    }
 }
@@ -0,0 +1,336 @@
+//! Discriminators for `core::net` addresses.
+//!
+//! Addresses are discriminated as the whole `u32` or `u128` they convert to
+//! rather than as a `Product` of their `U8` octets or `U16` segments.  The
+//! conversion is big-endian, so the two give the same groups in the same
+//! order, and `U32` and `U128` already sort their keys a byte at a time by
+//! radix.  Doing so over one flat slice is much faster than refining a
+//! product a component at a time, which discriminates each group of equal
+//! leading octets again.
+
+use alloc::vec::Vec;
+use core::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
+use discriminator::{Discriminator, EitherGroups, Map, Product, Sum, U128, U16, U32};
+use either::Either::{self, Left, Right};
+
+type SplitDesc = Map<fn(IpAddr) -> Either<Ipv4Addr, Ipv6Addr>, Sum<Ipv4, Ipv6>>;
+
+type MappedDesc = Map<fn(IpAddr) -> Ipv6Addr, Ipv6>;
+
+type SocketV4Desc = Map<fn(SocketAddrV4) -> (Ipv4Addr, u16), Product<Ipv4, U16>>;
+
+type SocketV6Desc = Map<fn(SocketAddrV6) -> ((Ipv6Addr, u16), (u32, u32)),
+                        Product<Product<Ipv6, U16>, Product<U32, U32>>>;
+
+type SocketDesc = Map<fn(SocketAddr) -> Either<SocketAddrV4, SocketAddrV6>,
+                      Sum<SocketV4, SocketV6>>;
+
+#[derive(Debug,Copy,Clone,Default)]
+pub struct Ipv4;
+
+impl Ipv4 {
+    pub fn new() -> Self {
+        Ipv4
+    }
+}
+
+impl<'a> Discriminator<'a, Ipv4Addr> for Ipv4 {
+    type Groups<V: 'a> = <U32 as Discriminator<'a, u32>>::Groups<V>;
+
+    fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> Self::Groups<V>
+        where I: IntoIterator,
+              I::Item: Into<(Ipv4Addr, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        const DESC: &Map<fn(Ipv4Addr) -> u32, U32> = &Map(u32::from, U32);
+        DESC.discriminate_sorted(pairs)
+    }
+}
+
+#[derive(Debug,Copy,Clone,Default)]
+pub struct Ipv6;
+
+impl Ipv6 {
+    pub fn new() -> Self {
+        Ipv6
+    }
+}
+
+impl<'a> Discriminator<'a, Ipv6Addr> for Ipv6 {
+    type Groups<V: 'a> = <U128 as Discriminator<'a, u128>>::Groups<V>;
+
+    fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> Self::Groups<V>
+        where I: IntoIterator,
+              I::Item: Into<(Ipv6Addr, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        const DESC: &Map<fn(Ipv6Addr) -> u128, U128> = &Map(u128::from, U128);
+        DESC.discriminate_sorted(pairs)
+    }
+}
+
+/// Discriminates IP addresses of either family.
+///
+/// By default, every IPv4 address comes before every IPv6 address, as with
+/// `IpAddr`'s `Ord` instance.  With `v4_mapped` set, IPv4 addresses are instead
+/// discriminated as their IPv4-mapped IPv6 addresses, so `1.2.3.4` and
+/// `::ffff:1.2.3.4` fall into the same group.
+#[derive(Debug,Copy,Clone,Default)]
+pub struct Ip {
+    pub v4_mapped: bool,
+}
+
+impl Ip {
+    pub fn v4_first() -> Self {
+        Ip { v4_mapped: false }
+    }
+
+    pub fn v4_mapped() -> Self {
+        Ip { v4_mapped: true }
+    }
+}
+
+impl<'a> Discriminator<'a, IpAddr> for Ip {
+    type Groups<V: 'a> = EitherGroups<<MappedDesc as Discriminator<'a, IpAddr>>::Groups<V>,
+                                      <SplitDesc as Discriminator<'a, IpAddr>>::Groups<V>>;
+
+    fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> Self::Groups<V>
+        where I: IntoIterator,
+              I::Item: Into<(IpAddr, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        fn split(a: IpAddr) -> Either<Ipv4Addr, Ipv6Addr> {
+            match a {
+                IpAddr::V4(a) => Left(a),
+                IpAddr::V6(a) => Right(a),
+            }
+        }
+        fn mapped(a: IpAddr) -> Ipv6Addr {
+            match a {
+                IpAddr::V4(a) => a.to_ipv6_mapped(),
+                IpAddr::V6(a) => a,
+            }
+        }
+        const SPLIT: &SplitDesc =
+            &Map(split,
+                 Sum { is_right_biased: false,
+                       left: Ipv4,
+                       right: Ipv6, });
+        const MAPPED: &MappedDesc = &Map(mapped, Ipv6);
+        if self.v4_mapped {
+            EitherGroups::Left(MAPPED.discriminate_sorted(pairs))
+        } else {
+            EitherGroups::Right(SPLIT.discriminate_sorted(pairs))
+        }
+    }
+}
+
+#[derive(Debug,Copy,Clone,Default)]
+pub struct SocketV4;
+
+impl SocketV4 {
+    pub fn new() -> Self {
+        SocketV4
+    }
+}
+
+impl<'a> Discriminator<'a, SocketAddrV4> for SocketV4 {
+    type Groups<V: 'a> = <SocketV4Desc as Discriminator<'a, SocketAddrV4>>::Groups<V>;
+
+    fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> Self::Groups<V>
+        where I: IntoIterator,
+              I::Item: Into<(SocketAddrV4, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        fn parts(a: SocketAddrV4) -> (Ipv4Addr, u16) {
+            (*a.ip(), a.port())
+        }
+        const DESC: &SocketV4Desc =
+            &Map(parts,
+                 Product { is_right_biased: false,
+                           left: Ipv4,
+                           right: U16, });
+        DESC.discriminate_sorted(pairs)
+    }
+}
+
+/// Discriminates IPv6 socket addresses by address, then port, then flow
+/// information, then scope ID, as with `SocketAddrV6`'s `Ord` instance.
+#[derive(Debug,Copy,Clone,Default)]
+pub struct SocketV6;
+
+impl SocketV6 {
+    pub fn new() -> Self {
+        SocketV6
+    }
+}
+
+impl<'a> Discriminator<'a, SocketAddrV6> for SocketV6 {
+    type Groups<V: 'a> = <SocketV6Desc as Discriminator<'a, SocketAddrV6>>::Groups<V>;
+
+    fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> Self::Groups<V>
+        where I: IntoIterator,
+              I::Item: Into<(SocketAddrV6, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        fn parts(a: SocketAddrV6) -> ((Ipv6Addr, u16), (u32, u32)) {
+            ((*a.ip(), a.port()), (a.flowinfo(), a.scope_id()))
+        }
+        const DESC: &SocketV6Desc = &Map(parts,
+                                         Product { is_right_biased: false,
+                                                   left: Product { is_right_biased: false,
+                                                                   left: Ipv6,
+                                                                   right: U16, },
+                                                   right: Product { is_right_biased: false,
+                                                                    left: U32,
+                                                                    right: U32, }, });
+        DESC.discriminate_sorted(pairs)
+    }
+}
+
+#[derive(Debug,Copy,Clone,Default)]
+pub struct Socket;
+
+impl Socket {
+    pub fn new() -> Self {
+        Socket
+    }
+}
+
+impl<'a> Discriminator<'a, SocketAddr> for Socket {
+    type Groups<V: 'a> = <SocketDesc as Discriminator<'a, SocketAddr>>::Groups<V>;
+
+    fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> Self::Groups<V>
+        where I: IntoIterator,
+              I::Item: Into<(SocketAddr, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        fn split(a: SocketAddr) -> Either<SocketAddrV4, SocketAddrV6> {
+            match a {
+                SocketAddr::V4(a) => Left(a),
+                SocketAddr::V6(a) => Right(a),
+            }
+        }
+        const DESC: &SocketDesc = &Map(split,
+                                       Sum { is_right_biased: false,
+                                             left: SocketV4,
+                                             right: SocketV6, });
+        DESC.discriminate_sorted(pairs)
+    }
+}
+
+fn v4_network(a: Ipv4Addr, len: u8) -> u32 {
+    match len {
+        0 => 0,
+        len if len >= 32 => u32::from(a),
+        len => u32::from(a) & (!0 << (32 - len)),
+    }
+}
+
+fn v6_network(a: Ipv6Addr, len: u8) -> u128 {
+    match len {
+        0 => 0,
+        len if len >= 128 => u128::from(a),
+        len => u128::from(a) & (!0 << (128 - len)),
+    }
+}
+
+/// Discriminates IPv4 addresses by their first `len` bits, so that addresses
+/// in the same `/len` network fall into the same group.
+#[derive(Debug,Copy,Clone,Default)]
+pub struct Ipv4Prefix {
+    pub len: u8,
+}
+
+impl Ipv4Prefix {
+    pub fn new(len: u8) -> Self {
+        Ipv4Prefix { len }
+    }
+}
+
+impl<'a> Discriminator<'a, Ipv4Addr> for Ipv4Prefix {
+    type Groups<V: 'a> = <U32 as Discriminator<'a, u32>>::Groups<V>;
+
+    fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> Self::Groups<V>
+        where I: IntoIterator,
+              I::Item: Into<(Ipv4Addr, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        const DESC: &U32 = &U32;
+        DESC.discriminate_sorted(pairs.into_iter().map(move |kv| {
+            let (k, v) = kv.into();
+            (v4_network(k, self.len), v)
+        }))
+    }
+}
+
+/// Discriminates IPv6 addresses by their first `len` bits, so that addresses
+/// in the same `/len` network fall into the same group.
+#[derive(Debug,Copy,Clone,Default)]
+pub struct Ipv6Prefix {
+    pub len: u8,
+}
+
+impl Ipv6Prefix {
+    pub fn new(len: u8) -> Self {
+        Ipv6Prefix { len }
+    }
+}
+
+impl<'a> Discriminator<'a, Ipv6Addr> for Ipv6Prefix {
+    type Groups<V: 'a> = <U128 as Discriminator<'a, u128>>::Groups<V>;
+
+    fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> Self::Groups<V>
+        where I: IntoIterator,
+              I::Item: Into<(Ipv6Addr, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        const DESC: &U128 = &U128;
+        DESC.discriminate_sorted(pairs.into_iter().map(move |kv| {
+            let (k, v) = kv.into();
+            (v6_network(k, self.len), v)
+        }))
+    }
+}
+
+/// Groups IPv4 addresses by their `/len` network, in address order.
+///
+/// Each group is returned alongside its network address.
+pub fn by_ipv4_prefix<V, I>(len: u8, addrs: I) -> Vec<(Ipv4Addr, Vec<V>)>
+    where I: IntoIterator<Item = (Ipv4Addr, V)>,
+          I::IntoIter: DoubleEndedIterator
+{
+    let addrs = addrs.into_iter().map(|(a, v)| (a, (a, v)));
+    Ipv4Prefix::new(len).discriminate_sorted(addrs)
+                        .filter_map(|group| {
+                                        let mut values = Vec::new();
+                                        let mut net = None;
+                                        for (a, v) in group {
+                                            net = net.or(Some(a));
+                                            values.push(v);
+                                        }
+                                        net.map(|a| (Ipv4Addr::from(v4_network(a, len)), values))
+                                    })
+                        .collect()
+}
+
+/// Groups IPv6 addresses by their `/len` network, in address order.
+///
+/// Each group is returned alongside its network address.
+pub fn by_ipv6_prefix<V, I>(len: u8, addrs: I) -> Vec<(Ipv6Addr, Vec<V>)>
+    where I: IntoIterator<Item = (Ipv6Addr, V)>,
+          I::IntoIter: DoubleEndedIterator
+{
+    let addrs = addrs.into_iter().map(|(a, v)| (a, (a, v)));
+    Ipv6Prefix::new(len).discriminate_sorted(addrs)
+                        .filter_map(|group| {
+                                        let mut values = Vec::new();
+                                        let mut net = None;
+                                        for (a, v) in group {
+                                            net = net.or(Some(a));
+                                            values.push(v);
+                                        }
+                                        net.map(|a| (Ipv6Addr::from(v6_network(a, len)), values))
+                                    })
+                        .collect()
+}
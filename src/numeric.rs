@@ -0,0 +1,193 @@
+use discriminator::{Invert, Sum, Trivial};
+use either::Either::{self, Left, Right};
+
+// negative magnitudes (`Left`) come first, in reverse, then zero, then
+// positive magnitudes
+type Signed<M> = Either<M, Either<(), M>>;
+
+type SignedDesc<D> = Sum<Invert<D>, Sum<Trivial, D>>;
+
+const fn signed_desc<D: Copy>(magnitude: D) -> SignedDesc<D> {
+    Sum { is_right_biased: false,
+          left: Invert(magnitude),
+          right: Sum { is_right_biased: false,
+                       left: Trivial,
+                       right: magnitude, }, }
+}
+
+fn signed<M>(is_negative: bool, is_zero: bool, magnitude: M) -> Signed<M> {
+    if is_zero {
+        Right(Left(()))
+    } else if is_negative {
+        Left(magnitude)
+    } else {
+        Right(Right(magnitude))
+    }
+}
+
+#[cfg(feature = "num-bigint")]
+pub use self::with_num_bigint::BigInteger;
+
+#[cfg(feature = "num-bigint")]
+mod with_num_bigint {
+    use alloc::vec;
+    use core::iter::Rev;
+    use discriminator::{Discriminator, Map, Prefix, Product, U64};
+    use num_bigint::{BigInt, BigUint, Sign, U64Digits};
+    use super::{Signed, SignedDesc, signed, signed_desc};
+
+    // equal lengths mean the digits can be compared lexicographically
+    type Magnitude<D> = (u64, Rev<D>);
+
+    type OwnedInteger = Signed<Magnitude<vec::IntoIter<u64>>>;
+
+    type MagnitudeDesc = Product<U64, Prefix<U64>>;
+
+    // the digits are only discriminated as the groups are taken, so the groups
+    // depend on whether they're borrowed
+    type MagnitudeGroups<'a, D, V> =
+        <MagnitudeDesc as Discriminator<'a, Magnitude<D>>>::Groups<V>;
+
+    type IntegerGroups<'a, D, V> =
+        <SignedDesc<MagnitudeDesc> as Discriminator<'a, Signed<Magnitude<D>>>>::Groups<V>;
+
+    const MAGNITUDE: MagnitudeDesc = Product { is_right_biased: false,
+                                               left: U64,
+                                               right: Prefix { depth: usize::MAX,
+                                                               element: U64, }, };
+
+    fn magnitude(n: &BigUint) -> Magnitude<U64Digits<'_>> {
+        let digits = n.iter_u64_digits();
+        (digits.len() as u64, digits.rev())
+    }
+
+    fn owned_magnitude(n: BigUint) -> Magnitude<vec::IntoIter<u64>> {
+        let digits = n.to_u64_digits();
+        (digits.len() as u64, digits.into_iter().rev())
+    }
+
+    fn integer(n: &BigInt) -> Signed<Magnitude<U64Digits<'_>>> {
+        signed(n.sign() == Sign::Minus, n.sign() == Sign::NoSign, magnitude(n.magnitude()))
+    }
+
+    fn owned_integer(n: BigInt) -> OwnedInteger {
+        let (sign, n) = n.into_parts();
+        signed(sign == Sign::Minus, sign == Sign::NoSign, owned_magnitude(n))
+    }
+
+    /// Discriminates arbitrary-precision integers numerically: by sign, then
+    /// by the number of digits in their magnitude, then by those digits.
+    #[derive(Debug,Copy,Clone,Default)]
+    pub struct BigInteger;
+
+    impl BigInteger {
+        pub fn new() -> Self {
+            BigInteger
+        }
+    }
+
+    impl<'a, 's: 'a> Discriminator<'a, &'s BigUint> for BigInteger {
+        type Groups<V: 'a> = MagnitudeGroups<'a, U64Digits<'s>, V>;
+
+        fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> Self::Groups<V>
+            where I: IntoIterator,
+                  I::Item: Into<(&'s BigUint, V)>,
+                  I::IntoIter: DoubleEndedIterator + 'a
+        {
+            type Desc = Map<for<'s> fn(&'s BigUint) -> Magnitude<U64Digits<'s>>, MagnitudeDesc>;
+            const DESC: &Desc = &Map(magnitude, MAGNITUDE);
+            DESC.discriminate_sorted(pairs)
+        }
+    }
+
+    impl<'a> Discriminator<'a, BigUint> for BigInteger {
+        type Groups<V: 'a> = MagnitudeGroups<'a, vec::IntoIter<u64>, V>;
+
+        fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> Self::Groups<V>
+            where I: IntoIterator,
+                  I::Item: Into<(BigUint, V)>,
+                  I::IntoIter: DoubleEndedIterator + 'a
+        {
+            type Desc = Map<fn(BigUint) -> Magnitude<vec::IntoIter<u64>>, MagnitudeDesc>;
+            const DESC: &Desc = &Map(owned_magnitude, MAGNITUDE);
+            DESC.discriminate_sorted(pairs)
+        }
+    }
+
+    impl<'a, 's: 'a> Discriminator<'a, &'s BigInt> for BigInteger {
+        type Groups<V: 'a> = IntegerGroups<'a, U64Digits<'s>, V>;
+
+        fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> Self::Groups<V>
+            where I: IntoIterator,
+                  I::Item: Into<(&'s BigInt, V)>,
+                  I::IntoIter: DoubleEndedIterator + 'a
+        {
+            type Desc = Map<for<'s> fn(&'s BigInt) -> Signed<Magnitude<U64Digits<'s>>>,
+                            SignedDesc<MagnitudeDesc>>;
+            const DESC: &Desc = &Map(integer, signed_desc(MAGNITUDE));
+            DESC.discriminate_sorted(pairs)
+        }
+    }
+
+    impl<'a> Discriminator<'a, BigInt> for BigInteger {
+        type Groups<V: 'a> = IntegerGroups<'a, vec::IntoIter<u64>, V>;
+
+        fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> Self::Groups<V>
+            where I: IntoIterator,
+                  I::Item: Into<(BigInt, V)>,
+                  I::IntoIter: DoubleEndedIterator + 'a
+        {
+            type Desc = Map<fn(BigInt) -> OwnedInteger, SignedDesc<MagnitudeDesc>>;
+            const DESC: &Desc = &Map(owned_integer, signed_desc(MAGNITUDE));
+            DESC.discriminate_sorted(pairs)
+        }
+    }
+}
+
+#[cfg(feature = "rust_decimal")]
+pub use self::with_rust_decimal::DecimalValue;
+
+#[cfg(feature = "rust_decimal")]
+mod with_rust_decimal {
+    use discriminator::{Discriminator, Map, Product, U128};
+    use rust_decimal::Decimal;
+    use super::{Signed, SignedDesc, signed, signed_desc};
+
+    type Desc = Map<fn(Decimal) -> Signed<(u128, u128)>, SignedDesc<Product<U128, U128>>>;
+
+    // the integer part, then the fractional part scaled up to the maximum
+    // precision, so that both always fit
+    fn value(d: Decimal) -> Signed<(u128, u128)> {
+        let mantissa = d.mantissa().unsigned_abs();
+        let unit = 10u128.pow(d.scale());
+        let fraction = mantissa % unit * 10u128.pow(Decimal::MAX_SCALE - d.scale());
+        signed(d.is_sign_negative(), d.is_zero(), (mantissa / unit, fraction))
+    }
+
+    /// Discriminates exact decimals by numeric value, so that `1.0` and
+    /// `1.00` fall into the same group.
+    #[derive(Debug,Copy,Clone,Default)]
+    pub struct DecimalValue;
+
+    impl DecimalValue {
+        pub fn new() -> Self {
+            DecimalValue
+        }
+    }
+
+    impl<'a> Discriminator<'a, Decimal> for DecimalValue {
+        type Groups<V: 'a> = <Desc as Discriminator<'a, Decimal>>::Groups<V>;
+
+        fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> Self::Groups<V>
+            where I: IntoIterator,
+                  I::Item: Into<(Decimal, V)>,
+                  I::IntoIter: DoubleEndedIterator + 'a
+        {
+            const DESC: &Desc = &Map(value,
+                                     signed_desc(Product { is_right_biased: false,
+                                                           left: U128,
+                                                           right: U128, }));
+            DESC.discriminate_sorted(pairs)
+        }
+    }
+}
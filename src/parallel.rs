@@ -0,0 +1,335 @@
+//! Discrimination split across rayon's thread pool.
+//!
+//! Every discriminator here produces exactly the groups that its sequential
+//! `discriminate_sorted` would, in the same order, and fails with the same
+//! error.  Inputs too short to be worth splitting are discriminated
+//! sequentially.
+
+use core::cmp;
+use core::mem::{self, MaybeUninit};
+use discriminator::{Bucketed, Discriminator, I128, I16, I32, I64, I8, Invert, Isize, Map, Natural,
+                    Product, Sum, U128, U16, U32, U64, U8, Usize, or_panic, radix_sorted,
+                    shared, single};
+use either::Either::{self, Left, Right};
+use error::DiscriminationError;
+use radix::Bits;
+use rayon;
+use rayon::prelude::*;
+
+pub trait ParDiscriminator<K: Send>: Sync {
+    /// Like `Discriminator::try_discriminate_sorted`, but splitting the work
+    /// across rayon's thread pool.
+    fn try_par_discriminate_sorted<V: Send>(&self, pairs: Vec<(K, V)>)
+        -> Result<Bucketed<V>, DiscriminationError>;
+
+    /// Like `Discriminator::discriminate_sorted`, but splitting the work
+    /// across rayon's thread pool.
+    fn par_discriminate_sorted<V: Send>(&self, pairs: Vec<(K, V)>) -> Bucketed<V> {
+        or_panic(self.try_par_discriminate_sorted(pairs))
+    }
+}
+
+impl<K: Send, D: ?Sized> ParDiscriminator<K> for &D where D: ParDiscriminator<K> {
+    fn try_par_discriminate_sorted<V: Send>(&self, pairs: Vec<(K, V)>)
+        -> Result<Bucketed<V>, DiscriminationError>
+    {
+        D::try_par_discriminate_sorted(self, pairs)
+    }
+}
+
+// chunks shorter than this are counted faster than they could be handed out
+const CHUNK_MIN: usize = 1 << 12;
+
+// the number of chunks to split `n` items into, each at least `min` long; at
+// most one means that it isn't worth splitting them
+fn chunks(n: usize, min: usize) -> usize {
+    // a few per thread, so that uneven ones balance out
+    cmp::min(n / cmp::max(min, CHUNK_MIN), 4 * rayon::current_num_threads())
+}
+
+// a buffer that every chunk writes its own places of
+struct Dest<W>(*mut MaybeUninit<W>);
+
+// SAFETY: no two chunks write the same place, so this is no different from
+// handing each its own part of the buffer
+unsafe impl<W: Send> Send for Dest<W> {}
+
+unsafe impl<W: Send> Sync for Dest<W> {}
+
+impl<W> Dest<W> {
+    // SAFETY: `i` must be in the buffer, and written by nothing else
+    unsafe fn write(&self, i: usize, value: W) {
+        unsafe { (*self.0.add(i)).write(value) };
+    }
+}
+
+// stably moves `values` into a buffer in order of their `keys`, returning the
+// run of every key that occurs
+//
+// every chunk counts its keys into a histogram of its own, and the histograms
+// are merged by giving each key's places to the chunks in input order
+#[allow(clippy::type_complexity)]
+fn scatter<W: Send>(keys: Vec<usize>, values: Vec<W>, limit: usize, chunks: usize)
+                    -> Result<(Box<[MaybeUninit<W>]>, Vec<(usize, usize)>), DiscriminationError>
+{
+    let size = keys.len().div_ceil(chunks);
+    let counts: Vec<Result<Vec<usize>, DiscriminationError>> =
+        keys.par_chunks(size)
+            .map(|chunk| {
+                     let mut count = vec![0; limit];
+                     for &k in chunk {
+                         match count.get_mut(k) {
+                             Some(count) => *count += 1,
+                             None => return Err(DiscriminationError::OutOfRange { key: k, limit }),
+                         }
+                     }
+                     Ok(count)
+                 })
+            .collect();
+    // the first error in input order, as the sequential discrimination finds
+    let mut offsets = counts.into_iter().collect::<Result<Vec<_>, _>>()?;
+
+    // turn the counts into offsets, key by key and chunk by chunk
+    let mut runs = Vec::new();
+    let mut total = 0;
+    for k in 0..limit {
+        let start = total;
+        for offset in &mut offsets {
+            total += mem::replace(&mut offset[k], total);
+        }
+        if total > start {
+            runs.push((start, total));
+        }
+    }
+
+    let mut buffer = Box::new_uninit_slice(keys.len());
+    let dest = Dest(buffer.as_mut_ptr());
+    values.into_par_iter()
+          .chunks(size)
+          .zip(keys.par_chunks(size))
+          .zip(offsets)
+          .for_each(|((values, keys), mut offsets)| {
+                        for (v, &k) in values.into_iter().zip(keys) {
+                            // SAFETY: the offsets of each chunk's keys start
+                            // where the previous chunk's end
+                            unsafe { dest.write(offsets[k], v) };
+                            offsets[k] += 1;
+                        }
+                    });
+
+    // every value has been moved in
+    Ok((buffer, runs))
+}
+
+impl<K: Into<usize> + Copy + Send> ParDiscriminator<K> for Natural {
+    fn try_par_discriminate_sorted<V: Send>(&self, pairs: Vec<(K, V)>)
+        -> Result<Bucketed<V>, DiscriminationError>
+    {
+        let chunks = chunks(pairs.len(), self.limit());
+        if chunks <= 1 {
+            return <Natural as Discriminator<K>>::try_discriminate_sorted(self, pairs);
+        }
+
+        let (keys, values) = pairs.into_par_iter().map(|(k, v)| (k.into(), v)).unzip();
+        let (buffer, runs) = scatter(keys, values, self.limit(), chunks)?;
+        Ok(shared(buffer, runs))
+    }
+}
+
+impl ParDiscriminator<u8> for U8 {
+    fn try_par_discriminate_sorted<V: Send>(&self, pairs: Vec<(u8, V)>)
+        -> Result<Bucketed<V>, DiscriminationError>
+    {
+        Natural::new(u8::MAX as usize + 1).try_par_discriminate_sorted(pairs)
+    }
+}
+
+impl ParDiscriminator<u16> for U16 {
+    fn try_par_discriminate_sorted<V: Send>(&self, pairs: Vec<(u16, V)>)
+        -> Result<Bucketed<V>, DiscriminationError>
+    {
+        Natural::new(u16::MAX as usize + 1).try_par_discriminate_sorted(pairs)
+    }
+}
+
+// splits the pairs by the most significant byte that varies between their
+// keys, then sorts each part by radix on its own
+fn par_radix_sorted<B: Bits + Send + Sync, V: Send>(pairs: Vec<(B, V)>) -> Bucketed<V> {
+    let chunks = chunks(pairs.len(), u8::MAX as usize + 1);
+    if chunks <= 1 {
+        return radix_sorted(pairs);
+    }
+
+    let (keys, values): (Vec<B>, Vec<V>) = pairs.into_par_iter().unzip();
+    let first = keys[0];
+    let varies = keys.par_iter()
+                     .map(|&k| {
+                              (0..B::BYTES).filter(|&i| k.byte(i) != first.byte(i))
+                                           .fold(0u32, |varies, i| varies | 1 << i)
+                          })
+                     .reduce(|| 0, |a, b| a | b);
+    if varies == 0 {
+        return single(values);
+    }
+
+    let i = varies.ilog2() as usize;
+    let bytes = keys.par_iter().map(|k| k.byte(i)).collect();
+    let pairs = keys.into_par_iter().zip(values).collect();
+    let (mut buffer, runs) = match scatter(bytes, pairs, u8::MAX as usize + 1, chunks) {
+        Ok(scattered) => scattered,
+        Err(_) => unreachable!("bytes are always in range"),
+    };
+
+    // the runs cover the buffer, in order
+    let mut parts = Vec::with_capacity(runs.len());
+    let mut rest = &mut buffer[..];
+    for (start, end) in runs {
+        let (part, tail) = mem::take(&mut rest).split_at_mut(end - start);
+        parts.push(part);
+        rest = tail;
+    }
+    let groups: Vec<Bucketed<V>> =
+        parts.into_par_iter()
+             .map(|part| {
+                      // SAFETY: every pair was moved in, and is moved out once
+                      radix_sorted(part.iter().map(|kv| unsafe { kv.assume_init_read() }).collect())
+                  })
+             .collect();
+    groups.into_iter().flatten().collect::<Vec<_>>().into_iter()
+}
+
+macro_rules! radix_par_discriminator {
+    ($name:ident, $uint:ty) => {
+        impl ParDiscriminator<$uint> for $name {
+            fn try_par_discriminate_sorted<V: Send>(&self, pairs: Vec<($uint, V)>)
+                -> Result<Bucketed<V>, DiscriminationError>
+            {
+                Ok(par_radix_sorted(pairs))
+            }
+        }
+    }
+}
+
+radix_par_discriminator!(U32, u32);
+radix_par_discriminator!(U64, u64);
+radix_par_discriminator!(U128, u128);
+
+impl ParDiscriminator<usize> for Usize {
+    fn try_par_discriminate_sorted<V: Send>(&self, pairs: Vec<(usize, V)>)
+        -> Result<Bucketed<V>, DiscriminationError>
+    {
+        Ok(par_radix_sorted(pairs.into_par_iter().map(|(k, v)| (k as u64, v)).collect()))
+    }
+}
+
+macro_rules! signed_par_discriminator {
+    ($name:ident, $int:ty, $uint:ty, $udesc:ident) => {
+        impl ParDiscriminator<$int> for $name {
+            fn try_par_discriminate_sorted<V: Send>(&self, pairs: Vec<($int, V)>)
+                -> Result<Bucketed<V>, DiscriminationError>
+            {
+                // flipping the sign bit puts the negatives first, in order
+                let pairs = pairs.into_par_iter()
+                                 .map(|(k, v)| ((k as $uint) ^ (1 << (<$uint>::BITS - 1)), v))
+                                 .collect();
+                $udesc.try_par_discriminate_sorted(pairs)
+            }
+        }
+    }
+}
+
+signed_par_discriminator!(I8, i8, u8, U8);
+signed_par_discriminator!(I16, i16, u16, U16);
+signed_par_discriminator!(I32, i32, u32, U32);
+signed_par_discriminator!(I64, i64, u64, U64);
+signed_par_discriminator!(I128, i128, u128, U128);
+
+impl ParDiscriminator<isize> for Isize {
+    fn try_par_discriminate_sorted<V: Send>(&self, pairs: Vec<(isize, V)>)
+        -> Result<Bucketed<V>, DiscriminationError>
+    {
+        I64.try_par_discriminate_sorted(pairs.into_par_iter().map(|(k, v)| (k as i64, v)).collect())
+    }
+}
+
+impl<K: Send, D: ?Sized> ParDiscriminator<K> for Invert<D> where D: ParDiscriminator<K> {
+    fn try_par_discriminate_sorted<V: Send>(&self, pairs: Vec<(K, V)>)
+        -> Result<Bucketed<V>, DiscriminationError>
+    {
+        let groups = self.0.try_par_discriminate_sorted(pairs)?;
+        Ok(groups.rev().collect::<Vec<_>>().into_iter())
+    }
+}
+
+impl<K: Send, J: Send, F, D: ?Sized> ParDiscriminator<K> for Map<F, D>
+    where D: ParDiscriminator<J>,
+          F: Fn(K) -> J + Sync
+{
+    fn try_par_discriminate_sorted<V: Send>(&self, pairs: Vec<(K, V)>)
+        -> Result<Bucketed<V>, DiscriminationError>
+    {
+        self.1.try_par_discriminate_sorted(pairs.into_par_iter()
+                                                .map(|(k, v)| ((self.0)(k), v))
+                                                .collect())
+    }
+}
+
+/// Both branches are discriminated at once.
+impl<J: Send, K: Send, L, R: ?Sized> ParDiscriminator<Either<J, K>> for Sum<L, R>
+    where L: ParDiscriminator<J>,
+          R: ParDiscriminator<K>
+{
+    fn try_par_discriminate_sorted<V: Send>(&self, pairs: Vec<(Either<J, K>, V)>)
+        -> Result<Bucketed<V>, DiscriminationError>
+    {
+        let (left_pairs, right_pairs): (Vec<_>, Vec<_>) =
+            pairs.into_par_iter().partition_map(|(k, v)| match k {
+                                                    Left(j) => Left((j, v)),
+                                                    Right(k) => Right((k, v)),
+                                                });
+        let (left, right) = rayon::join(|| self.left.try_par_discriminate_sorted(left_pairs),
+                                        || self.right.try_par_discriminate_sorted(right_pairs));
+        let groups = if self.is_right_biased {
+            let right = right?;
+            right.chain(left?).collect::<Vec<_>>()
+        } else {
+            left?.chain(right?).collect::<Vec<_>>()
+        };
+        Ok(groups.into_iter())
+    }
+}
+
+// refines every group by `desc`, all at once
+fn refine<K, V, D>(desc: &D, groups: Bucketed<(K, V)>) -> Result<Bucketed<V>, DiscriminationError>
+    where K: Send,
+          V: Send,
+          D: ?Sized + ParDiscriminator<K>
+{
+    let groups: Vec<Result<Bucketed<V>, DiscriminationError>> =
+        groups.collect::<Vec<_>>()
+              .into_par_iter()
+              .map(|group| desc.try_par_discriminate_sorted(group.collect()))
+              .collect();
+    // the first error in group order, as the sequential discrimination finds
+    let groups = groups.into_iter().collect::<Result<Vec<_>, _>>()?;
+    Ok(groups.into_iter().flatten().collect::<Vec<_>>().into_iter())
+}
+
+/// The subgroups of every group of the first component are discriminated at
+/// once.
+impl<J: Send, K: Send, L, R: ?Sized> ParDiscriminator<(J, K)> for Product<L, R>
+    where L: ParDiscriminator<J>,
+          R: ParDiscriminator<K>
+{
+    fn try_par_discriminate_sorted<V: Send>(&self, pairs: Vec<((J, K), V)>)
+        -> Result<Bucketed<V>, DiscriminationError>
+    {
+        if self.is_right_biased {
+            let pairs = pairs.into_par_iter().map(|((j, k), v)| (k, (j, v))).collect();
+            refine(&self.left, self.right.try_par_discriminate_sorted(pairs)?)
+        } else {
+            let pairs = pairs.into_par_iter().map(|((j, k), v)| (j, (k, v))).collect();
+            refine(&self.right, self.left.try_par_discriminate_sorted(pairs)?)
+        }
+    }
+}
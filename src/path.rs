@@ -0,0 +1,188 @@
+use discriminator::{Bucketed, Discriminator, Map, Prefix, U8};
+use std::ffi::{OsStr, OsString};
+use std::iter::{self, Cloned};
+use std::path::{self, Component, Path, PathBuf};
+use std::slice;
+use std::vec;
+
+type Bytes = Map<for<'s> fn(&'s [u8]) -> Cloned<slice::Iter<'s, u8>>, Prefix<U8>>;
+
+type Components<'s> = iter::Map<path::Components<'s>, fn(Component<'s>) -> &'s [u8]>;
+
+const BYTES: Bytes = Map(bytes,
+                         Prefix { depth: usize::MAX,
+                                  element: U8, });
+
+fn bytes(s: &[u8]) -> Cloned<slice::Iter<'_, u8>> {
+    s.iter().cloned()
+}
+
+fn os_bytes(s: &OsStr) -> Cloned<slice::Iter<'_, u8>> {
+    bytes(s.as_encoded_bytes())
+}
+
+fn component_bytes(c: Component<'_>) -> &[u8] {
+    c.as_os_str().as_encoded_bytes()
+}
+
+fn components(p: &Path) -> Components<'_> {
+    p.components().map(component_bytes as fn(Component<'_>) -> &[u8])
+}
+
+/// Discriminates paths lexicographically by their components, comparing each
+/// component by its raw OS bytes.
+///
+/// Since `Path::components` normalizes away redundant separators and `.`
+/// components, `a//b/` and `a/./b` fall into the same group as `a/b`.
+#[derive(Debug,Copy,Clone,Default)]
+pub struct PathComponents;
+
+impl PathComponents {
+    pub fn new() -> Self {
+        PathComponents
+    }
+}
+
+impl<'a, 's: 'a> Discriminator<'a, &'s Path> for PathComponents {
+    type Groups<V: 'a> = Bucketed<V>;
+
+    fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> Self::Groups<V>
+        where I: IntoIterator,
+              I::Item: Into<(&'s Path, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        const DESC: &Map<for<'s> fn(&'s Path) -> Components<'s>, Prefix<Bytes>> =
+            &Map(components,
+                 Prefix { depth: usize::MAX,
+                          element: BYTES, });
+        DESC.discriminate_sorted(pairs)
+    }
+}
+
+impl<'a, 's: 'a> Discriminator<'a, &'s PathBuf> for PathComponents {
+    type Groups<V: 'a> = Bucketed<V>;
+
+    fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> Self::Groups<V>
+        where I: IntoIterator,
+              I::Item: Into<(&'s PathBuf, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        fn conv(p: &PathBuf) -> &Path {
+            p
+        }
+        const DESC: &Map<for<'s> fn(&'s PathBuf) -> &'s Path, PathComponents> =
+            &Map(conv, PathComponents);
+        DESC.discriminate_sorted(pairs)
+    }
+}
+
+impl<'a> Discriminator<'a, PathBuf> for PathComponents {
+    type Groups<V: 'a> = Bucketed<V>;
+
+    fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> Self::Groups<V>
+        where I: IntoIterator,
+              I::Item: Into<(PathBuf, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        // the components can't borrow from a key that is about to be dropped
+        fn conv(p: PathBuf) -> Vec<vec::IntoIter<u8>> {
+            p.components()
+             .map(|c| component_bytes(c).to_vec().into_iter())
+             .collect()
+        }
+        type Desc = Map<fn(PathBuf) -> Vec<vec::IntoIter<u8>>, Prefix<Prefix<U8>>>;
+        const DESC: &Desc = &Map(conv,
+                                 Prefix { depth: usize::MAX,
+                                          element: Prefix { depth: usize::MAX,
+                                                            element: U8, }, });
+        DESC.discriminate_sorted(pairs)
+    }
+}
+
+/// Discriminates OS strings lexicographically by their raw OS bytes.
+#[derive(Debug,Copy,Clone,Default)]
+pub struct OsBytes;
+
+impl OsBytes {
+    pub fn new() -> Self {
+        OsBytes
+    }
+}
+
+impl<'a, 's: 'a> Discriminator<'a, &'s OsStr> for OsBytes {
+    type Groups<V: 'a> = Bucketed<V>;
+
+    fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> Self::Groups<V>
+        where I: IntoIterator,
+              I::Item: Into<(&'s OsStr, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        type Desc = Map<for<'s> fn(&'s OsStr) -> Cloned<slice::Iter<'s, u8>>, Prefix<U8>>;
+        const DESC: &Desc =
+            &Map(os_bytes,
+                 Prefix { depth: usize::MAX,
+                          element: U8, });
+        DESC.discriminate_sorted(pairs)
+    }
+}
+
+impl<'a, 's: 'a> Discriminator<'a, &'s OsString> for OsBytes {
+    type Groups<V: 'a> = Bucketed<V>;
+
+    fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> Self::Groups<V>
+        where I: IntoIterator,
+              I::Item: Into<(&'s OsString, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        fn conv(s: &OsString) -> &OsStr {
+            s
+        }
+        const DESC: &Map<for<'s> fn(&'s OsString) -> &'s OsStr, OsBytes> = &Map(conv, OsBytes);
+        DESC.discriminate_sorted(pairs)
+    }
+}
+
+impl<'a> Discriminator<'a, OsString> for OsBytes {
+    type Groups<V: 'a> = Bucketed<V>;
+
+    fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> Self::Groups<V>
+        where I: IntoIterator,
+              I::Item: Into<(OsString, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        fn conv(s: OsString) -> vec::IntoIter<u8> {
+            s.into_encoded_bytes().into_iter()
+        }
+        type Desc = Map<fn(OsString) -> vec::IntoIter<u8>, Prefix<U8>>;
+        const DESC: &Desc =
+            &Map(conv,
+                 Prefix { depth: usize::MAX,
+                          element: U8, });
+        DESC.discriminate_sorted(pairs)
+    }
+}
+
+/// Groups paths by their parent directory, in `PathComponents` order.
+///
+/// Each group is returned alongside its directory; paths without a parent
+/// (such as `/` or the empty path) are grouped under the empty path.
+pub fn by_directory<'s, V, I>(paths: I) -> Vec<(&'s Path, Vec<V>)>
+    where I: IntoIterator<Item = (&'s Path, V)>,
+          I::IntoIter: DoubleEndedIterator
+{
+    let paths = paths.into_iter().map(|(path, v)| {
+                                          let dir = path.parent().unwrap_or_else(|| Path::new(""));
+                                          (dir, (dir, v))
+                                      });
+    PathComponents.discriminate_sorted(paths)
+                  .map(|group| {
+                           let mut dir = None;
+                           let values = group.map(|(d, v)| {
+                                                      dir = dir.or(Some(d));
+                                                      v
+                                                  })
+                                             .collect();
+                           (dir.unwrap_or_else(|| Path::new("")), values)
+                       })
+                  .collect()
+}
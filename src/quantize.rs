@@ -0,0 +1,222 @@
+use alloc::vec::Vec;
+use core::time::Duration;
+use discriminator::{Bucketed, Discriminator, I64, Natural};
+#[cfg(feature = "std")]
+use std::time::SystemTime;
+
+/// Keys that can be split into fixed-width bins.
+pub trait Quantity: Copy {
+    /// The width of a bin, whose default is the zero width.
+    type Width: Copy + PartialOrd + Default;
+
+    /// The index of the bin containing `self`, where bin `0` starts at
+    /// `origin` and each bin is `width` wide.  Bins before `origin` have
+    /// negative indices.
+    fn bin(self, origin: Self, width: Self::Width) -> i64;
+}
+
+fn floor_div(offset: i128, width: i128) -> i64 {
+    let bin = offset.div_euclid(width);
+    bin.clamp(i64::MIN as i128, i64::MAX as i128) as i64
+}
+
+impl Quantity for i64 {
+    type Width = u64;
+
+    fn bin(self, origin: i64, width: u64) -> i64 {
+        floor_div(self as i128 - origin as i128, width as i128)
+    }
+}
+
+impl Quantity for u64 {
+    type Width = u64;
+
+    fn bin(self, origin: u64, width: u64) -> i64 {
+        floor_div(self as i128 - origin as i128, width as i128)
+    }
+}
+
+impl Quantity for f64 {
+    type Width = f64;
+
+    fn bin(self, origin: f64, width: f64) -> i64 {
+        let bin = (self - origin) / width;
+        if bin.is_nan() {
+            return i64::MAX;
+        }
+        // `floor` needs `std`, but truncating only rounds the wrong way below
+        // zero
+        let truncated = bin as i64;
+        if (truncated as f64) > bin {
+            truncated.saturating_sub(1)
+        } else {
+            truncated
+        }
+    }
+}
+
+impl Quantity for Duration {
+    type Width = Duration;
+
+    fn bin(self, origin: Duration, width: Duration) -> i64 {
+        floor_div(self.as_nanos() as i128 - origin.as_nanos() as i128,
+                  width.as_nanos() as i128)
+    }
+}
+
+#[cfg(feature = "std")]
+impl Quantity for SystemTime {
+    type Width = Duration;
+
+    fn bin(self, origin: SystemTime, width: Duration) -> i64 {
+        let offset = match self.duration_since(origin) {
+            Ok(after) => after.as_nanos() as i128,
+            Err(before) => -(before.duration().as_nanos() as i128),
+        };
+        floor_div(offset, width.as_nanos() as i128)
+    }
+}
+
+/// Discriminates keys by which fixed-width bin they fall into, with bins
+/// ordered by their index.
+///
+/// The width must be positive: `new` panics on a zero, negative or NaN one,
+/// which would otherwise divide by zero or reverse the bins.
+#[derive(Debug,Copy,Clone)]
+pub struct Quantize<T: Quantity> {
+    pub origin: T,
+    pub width: T::Width,
+}
+
+impl<T: Quantity> Quantize<T> {
+    pub fn new(origin: T, width: T::Width) -> Self {
+        assert!(width > T::Width::default(), "`Quantize` widths must be positive");
+        Quantize { origin,
+                   width, }
+    }
+
+    pub fn bin(&self, key: T) -> i64 {
+        key.bin(self.origin, self.width)
+    }
+
+    /// Groups `pairs` by bin, returning each group alongside its bin index.
+    pub fn bins<V, I>(&self, pairs: I) -> Vec<(i64, Vec<V>)>
+        where I: IntoIterator<Item = (T, V)>,
+              I::IntoIter: DoubleEndedIterator
+    {
+        const DESC: &I64 = &I64;
+        DESC.discriminate_sorted(pairs.into_iter().map(|(k, v)| {
+                                                           let bin = self.bin(k);
+                                                           (bin, (bin, v))
+                                                       }))
+            .filter_map(labelled)
+            .collect()
+    }
+}
+
+#[cfg(feature = "std")]
+impl Quantize<SystemTime> {
+    pub fn minutes() -> Self {
+        Quantize::new(SystemTime::UNIX_EPOCH, Duration::from_secs(60))
+    }
+
+    pub fn hours() -> Self {
+        Quantize::new(SystemTime::UNIX_EPOCH, Duration::from_secs(60 * 60))
+    }
+
+    pub fn days() -> Self {
+        Quantize::new(SystemTime::UNIX_EPOCH, Duration::from_secs(24 * 60 * 60))
+    }
+
+    /// Weeks starting on Monday at midnight UTC, as in ISO 8601.
+    pub fn iso_weeks() -> Self {
+        // the epoch was a Thursday
+        Quantize::new(SystemTime::UNIX_EPOCH + Duration::from_secs(4 * 24 * 60 * 60),
+                      Duration::from_secs(7 * 24 * 60 * 60))
+    }
+}
+
+impl<'a, T: Quantity + 'a> Discriminator<'a, T> for Quantize<T> {
+    type Groups<V: 'a> = <I64 as Discriminator<'a, i64>>::Groups<V>;
+
+    fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> Self::Groups<V>
+        where I: IntoIterator,
+              I::Item: Into<(T, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        const DESC: &I64 = &I64;
+        DESC.discriminate_sorted(pairs.into_iter().map(move |kv| {
+            let (k, v) = kv.into();
+            (self.bin(k), v)
+        }))
+    }
+}
+
+/// Discriminates keys by where they fall in a sorted table of boundaries.
+///
+/// Bin `0` holds the keys below the first boundary, bin `i` the keys at or
+/// above boundary `i - 1` but below boundary `i`, and the last bin the keys
+/// at or above the last boundary.
+#[derive(Debug,Clone)]
+pub struct Buckets<T> {
+    boundaries: Vec<T>,
+    desc: Natural,
+}
+
+impl<T: PartialOrd> Buckets<T> {
+    pub fn new(boundaries: Vec<T>) -> Self {
+        debug_assert!(boundaries.windows(2).all(|w| w[0] <= w[1]));
+        let desc = Natural::new(Ord::max(boundaries.len() + 1, 2));
+        Buckets { boundaries,
+                  desc, }
+    }
+
+    pub fn boundaries(&self) -> &[T] {
+        &self.boundaries
+    }
+
+    pub fn bin(&self, key: &T) -> usize {
+        self.boundaries.partition_point(|b| b <= key)
+    }
+
+    /// Groups `pairs` by bin, returning each group alongside its bin index.
+    pub fn bins<V, I>(&self, pairs: I) -> Vec<(usize, Vec<V>)>
+        where I: IntoIterator<Item = (T, V)>,
+              I::IntoIter: DoubleEndedIterator
+    {
+        self.desc
+            .discriminate_sorted(pairs.into_iter().map(|(k, v)| {
+                                                           let bin = self.bin(&k);
+                                                           (bin, (bin, v))
+                                                       }))
+            .filter_map(labelled)
+            .collect()
+    }
+}
+
+impl<'a, T: PartialOrd + 'a> Discriminator<'a, T> for Buckets<T> {
+    type Groups<V: 'a> = Bucketed<V>;
+
+    fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> Self::Groups<V>
+        where I: IntoIterator,
+              I::Item: Into<(T, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        self.desc.discriminate_sorted(pairs.into_iter().map(move |kv| {
+            let (k, v) = kv.into();
+            (self.bin(&k), v)
+        }))
+    }
+}
+
+fn labelled<B, V, G>(group: G) -> Option<(B, Vec<V>)>
+    where G: Iterator<Item = (B, V)>
+{
+    let mut bin = None;
+    let values = group.map(|(b, v)| {
+                               bin.get_or_insert(b);
+                               v
+                           })
+                      .collect();
+    bin.map(|b| (b, values))
+}
@@ -0,0 +1,179 @@
+use alloc::vec::Vec;
+use core::mem;
+use core::ptr;
+
+/// Unsigned integers that can be sorted a byte at a time.
+pub trait Bits: Copy + Eq {
+    const BYTES: usize;
+
+    /// The `i`th byte, counting from the least significant.
+    fn byte(self, i: usize) -> usize;
+}
+
+macro_rules! bits {
+    ($uint:ty) => {
+        impl Bits for $uint {
+            const BYTES: usize = mem::size_of::<$uint>();
+
+            fn byte(self, i: usize) -> usize {
+                (self >> (8 * i)) as usize & 0xff
+            }
+        }
+    }
+}
+
+bits!(u8);
+bits!(u16);
+bits!(u32);
+bits!(u64);
+bits!(u128);
+
+/// Sorts `pairs` stably by key.
+pub fn sort_pairs<B: Bits, V>(pairs: &mut [(B, V)]) {
+    radix_sort(pairs, B::BYTES, |kv, p| kv.0.byte(B::BYTES - 1 - p) as u8);
+}
+
+// slices of more than this many bytes are first split by their leading digit,
+// so that the passes over each part run in cache; far fewer under Miri, so that
+// short tests reach the split too
+const CACHED: usize = if cfg!(miri) { 1 << 8 } else { 1 << 20 };
+
+/// Sorts `items` stably by `digits` digits of a byte each, as `digit` reads
+/// them, most significant first, moving them back and forth between the
+/// slice and a buffer as long as it.
+///
+/// Long slices are split by their leading digits first, and the parts short
+/// enough to fit in cache are then sorted from their least significant digit
+/// up.
+pub fn radix_sort<T, F>(items: &mut [T], digits: usize, mut digit: F)
+    where F: FnMut(&T, usize) -> u8
+{
+    let mut spare: Vec<T> = Vec::with_capacity(items.len());
+    let places = Places { items: items.as_mut_ptr(),
+                          spare: spare.as_mut_ptr(),
+                          len: items.len(),
+                          is_spare: false, };
+    // SAFETY: the buffer has room for every element of the slice
+    unsafe { split_sort(&mut digit, places, 0, digits) }
+}
+
+// sorts the elements in `places` by their digits from `position` on, which
+// are all that differ
+unsafe fn split_sort<T, F>(digit: &mut F, mut places: Places<T>, position: usize, digits: usize)
+    where F: FnMut(&T, usize) -> u8
+{
+    let n = places.len;
+    if n <= 1 || position >= digits {
+        return;
+    }
+    if n.saturating_mul(mem::size_of::<T>()) <= CACHED {
+        return lsd_sort(digit, places, position, digits);
+    }
+
+    let mut count = [0; 256];
+    let (from, _) = places.source_and_target();
+    for j in 0..n {
+        count[digit(&*from.add(j), position) as usize] += 1;
+    }
+    if !count.contains(&n) {
+        scatter(digit, &places, &count, position);
+        places.is_spare = !places.is_spare;
+    }
+
+    // each part is sorted from wherever the split left it, and split off
+    // first, so that `places` only moves back those that haven't been
+    for &count in &count {
+        let part = Places { items: places.items,
+                            spare: places.spare,
+                            len: count,
+                            is_spare: places.is_spare, };
+        places.items = places.items.add(count);
+        places.spare = places.spare.add(count);
+        places.len -= count;
+        split_sort(digit, part, position + 1, digits);
+    }
+}
+
+// sorts the elements in `places` by their digits from `position` on, one at a
+// time from the least significant
+//
+// the histograms of every digit are counted in a single pass up front, which
+// also shows the digits that are the same in every key, so that their passes
+// can be skipped
+unsafe fn lsd_sort<T, F>(digit: &mut F, mut places: Places<T>, position: usize, digits: usize)
+    where F: FnMut(&T, usize) -> u8
+{
+    let mut counts = vec![[0; 256]; digits - position];
+    let (from, _) = places.source_and_target();
+    for j in 0..places.len {
+        let item = &*from.add(j);
+        for (p, count) in (position..digits).zip(counts.iter_mut()) {
+            count[digit(item, p) as usize] += 1;
+        }
+    }
+
+    for (p, count) in (position..digits).zip(counts.iter()).rev() {
+        if !count.contains(&places.len) {
+            scatter(digit, &places, count, p);
+            places.is_spare = !places.is_spare;
+        }
+    }
+}
+
+// moves every element from where `places` has them to the other place, in
+// order of their digits at `position`, which `count` must have counted
+unsafe fn scatter<T, F>(digit: &mut F, places: &Places<T>, count: &[usize; 256], position: usize)
+    where F: FnMut(&T, usize) -> u8
+{
+    let mut offsets = [0; 256];
+    let mut ends = [0; 256];
+    let mut total = 0;
+    for ((offset, end), &count) in offsets.iter_mut().zip(ends.iter_mut()).zip(count) {
+        *offset = total;
+        total += count;
+        *end = total;
+    }
+
+    let (from, to) = places.source_and_target();
+    for j in 0..places.len {
+        let d = digit(&*from.add(j), position) as usize;
+        let offset = &mut offsets[d];
+        // `digit` may not read the same digits as it did when they were counted
+        assert!(*offset < ends[d], "digits changed while they were being sorted");
+        // each digit fills only its own places, which start where the previous
+        // digit's end, so every element is moved exactly once to a distinct
+        // place
+        ptr::copy_nonoverlapping(from.add(j), to.add(*offset), 1);
+        *offset += 1;
+    }
+}
+
+// where some of the elements being sorted are, between the slice and the
+// buffer; should they be in the buffer when this is dropped, including if
+// `digit` panics, they're moved back into the slice
+struct Places<T> {
+    items: *mut T,
+    spare: *mut T,
+    len: usize,
+    is_spare: bool,
+}
+
+impl<T> Places<T> {
+    fn source_and_target(&self) -> (*mut T, *mut T) {
+        if self.is_spare {
+            (self.spare, self.items)
+        } else {
+            (self.items, self.spare)
+        }
+    }
+}
+
+impl<T> Drop for Places<T> {
+    fn drop(&mut self) {
+        if self.is_spare {
+            // SAFETY: these elements are all in the buffer, and what's left of
+            // them in the slice has already been moved out
+            unsafe { ptr::copy_nonoverlapping(self.spare, self.items, self.len) };
+        }
+    }
+}
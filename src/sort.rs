@@ -0,0 +1,402 @@
+use alloc::vec::Vec;
+use core::cmp::{Ordering, Reverse};
+use core::mem;
+use core::num::Wrapping;
+use discriminator::{Discriminator, I128, I16, I32, I64, I8, Invert, Isize, Natural, Product,
+                    Reversed, U128, U16, U32, U64, U8, Usize, Wrapped, or_panic};
+use error::DiscriminationError;
+use radix::radix_sort;
+
+/// Discriminators whose keys break down into a fixed number of digits, most
+/// significant first, each taking one of a bounded number of values.
+///
+/// This is what lets `disc_sort_unstable_by_key` sort a slice in place, one
+/// digit at a time.
+pub trait Radix<K> {
+    /// The number of digits in every key.
+    fn digits(&self) -> usize;
+
+    /// The number of values that the digit at `position` can take.
+    fn radix(&self, position: usize) -> usize;
+
+    /// The digit of `key` at `position`, which must be less than its radix.
+    ///
+    /// Panics if `key` has no such digit, as `Natural` keys that aren't below
+    /// its limit don't.
+    fn digit(&self, key: &K, position: usize) -> usize;
+
+    /// Like `digit`, but reports keys that have no such digit instead of
+    /// panicking.
+    ///
+    /// Radixes that have every digit of every key needn't override this.
+    fn try_digit(&self, key: &K, position: usize) -> Result<usize, DiscriminationError> {
+        Ok(self.digit(key, position))
+    }
+
+    /// Whether every digit is a byte, in which case keys of up to 16 digits
+    /// can also be sorted from their least significant digit up.
+    fn is_bytewise(&self) -> bool {
+        false
+    }
+}
+
+impl<K, R: ?Sized + Radix<K>> Radix<K> for &R {
+    fn digits(&self) -> usize {
+        R::digits(self)
+    }
+
+    fn radix(&self, position: usize) -> usize {
+        R::radix(self, position)
+    }
+
+    fn digit(&self, key: &K, position: usize) -> usize {
+        R::digit(self, key, position)
+    }
+
+    fn try_digit(&self, key: &K, position: usize) -> Result<usize, DiscriminationError> {
+        R::try_digit(self, key, position)
+    }
+
+    fn is_bytewise(&self) -> bool {
+        R::is_bytewise(self)
+    }
+}
+
+impl<K: Into<usize> + Copy> Radix<K> for Natural {
+    fn digits(&self) -> usize {
+        1
+    }
+
+    fn radix(&self, _: usize) -> usize {
+        self.limit()
+    }
+
+    fn digit(&self, key: &K, position: usize) -> usize {
+        or_panic(self.try_digit(key, position))
+    }
+
+    fn try_digit(&self, key: &K, _: usize) -> Result<usize, DiscriminationError> {
+        let key = (*key).into();
+        if key >= self.limit() {
+            return Err(DiscriminationError::OutOfRange { key,
+                                                         limit: self.limit(), });
+        }
+        Ok(key)
+    }
+}
+
+// bytewise, so that no level needs more than 256 buckets
+macro_rules! unsigned_radix {
+    ($desc:ident, $uint:ty) => {
+        impl Radix<$uint> for $desc {
+            fn digits(&self) -> usize {
+                mem::size_of::<$uint>()
+            }
+
+            fn radix(&self, _: usize) -> usize {
+                256
+            }
+
+            fn digit(&self, key: &$uint, position: usize) -> usize {
+                let shift = 8 * (mem::size_of::<$uint>() - 1 - position);
+                (*key >> shift) as usize & 0xff
+            }
+
+            fn is_bytewise(&self) -> bool {
+                true
+            }
+        }
+    }
+}
+
+unsigned_radix!(U8, u8);
+unsigned_radix!(U16, u16);
+unsigned_radix!(U32, u32);
+unsigned_radix!(U64, u64);
+unsigned_radix!(U128, u128);
+unsigned_radix!(Usize, usize);
+
+macro_rules! signed_radix {
+    ($desc:ident, $int:ty, $uint:ty, $udesc:ident) => {
+        impl Radix<$int> for $desc {
+            fn digits(&self) -> usize {
+                mem::size_of::<$int>()
+            }
+
+            fn radix(&self, _: usize) -> usize {
+                256
+            }
+
+            // flipping the sign bit puts the negatives first, in order
+            fn digit(&self, key: &$int, position: usize) -> usize {
+                let key = (*key as $uint) ^ (1 << (<$uint>::BITS - 1));
+                $udesc.digit(&key, position)
+            }
+
+            fn is_bytewise(&self) -> bool {
+                true
+            }
+        }
+    }
+}
+
+signed_radix!(I8, i8, u8, U8);
+signed_radix!(I16, i16, u16, U16);
+signed_radix!(I32, i32, u32, U32);
+signed_radix!(I64, i64, u64, U64);
+signed_radix!(I128, i128, u128, U128);
+signed_radix!(Isize, isize, usize, Usize);
+
+impl<K, D: ?Sized + Radix<K>> Radix<K> for Invert<D> {
+    fn digits(&self) -> usize {
+        self.0.digits()
+    }
+
+    fn radix(&self, position: usize) -> usize {
+        self.0.radix(position)
+    }
+
+    fn digit(&self, key: &K, position: usize) -> usize {
+        self.0.radix(position) - 1 - self.0.digit(key, position)
+    }
+
+    fn try_digit(&self, key: &K, position: usize) -> Result<usize, DiscriminationError> {
+        Ok(self.0.radix(position) - 1 - self.0.try_digit(key, position)?)
+    }
+
+    fn is_bytewise(&self) -> bool {
+        self.0.is_bytewise()
+    }
+}
+
+impl<K, D: ?Sized + Radix<K>> Radix<Reverse<K>> for Reversed<D> {
+    fn digits(&self) -> usize {
+        self.0.digits()
+    }
+
+    fn radix(&self, position: usize) -> usize {
+        self.0.radix(position)
+    }
+
+    fn digit(&self, key: &Reverse<K>, position: usize) -> usize {
+        self.0.radix(position) - 1 - self.0.digit(&key.0, position)
+    }
+
+    fn try_digit(&self, key: &Reverse<K>, position: usize) -> Result<usize, DiscriminationError> {
+        Ok(self.0.radix(position) - 1 - self.0.try_digit(&key.0, position)?)
+    }
+
+    fn is_bytewise(&self) -> bool {
+        self.0.is_bytewise()
+    }
+}
+
+impl<K, D: ?Sized + Radix<K>> Radix<Wrapping<K>> for Wrapped<D> {
+    fn digits(&self) -> usize {
+        self.0.digits()
+    }
+
+    fn radix(&self, position: usize) -> usize {
+        self.0.radix(position)
+    }
+
+    fn digit(&self, key: &Wrapping<K>, position: usize) -> usize {
+        self.0.digit(&key.0, position)
+    }
+
+    fn try_digit(&self, key: &Wrapping<K>, position: usize)
+                 -> Result<usize, DiscriminationError>
+    {
+        self.0.try_digit(&key.0, position)
+    }
+
+    fn is_bytewise(&self) -> bool {
+        self.0.is_bytewise()
+    }
+}
+
+impl<J, K, L: Radix<J>, R: ?Sized + Radix<K>> Radix<(J, K)> for Product<L, R> {
+    fn digits(&self) -> usize {
+        self.left.digits() + self.right.digits()
+    }
+
+    fn radix(&self, position: usize) -> usize {
+        if self.is_right_biased {
+            let digits = self.right.digits();
+            if position < digits {
+                self.right.radix(position)
+            } else {
+                self.left.radix(position - digits)
+            }
+        } else {
+            let digits = self.left.digits();
+            if position < digits {
+                self.left.radix(position)
+            } else {
+                self.right.radix(position - digits)
+            }
+        }
+    }
+
+    fn digit(&self, key: &(J, K), position: usize) -> usize {
+        or_panic(self.try_digit(key, position))
+    }
+
+    fn try_digit(&self, key: &(J, K), position: usize) -> Result<usize, DiscriminationError> {
+        if self.is_right_biased {
+            let digits = self.right.digits();
+            if position < digits {
+                self.right.try_digit(&key.1, position)
+            } else {
+                self.left.try_digit(&key.0, position - digits)
+            }
+        } else {
+            let digits = self.left.digits();
+            if position < digits {
+                self.left.try_digit(&key.0, position)
+            } else {
+                self.right.try_digit(&key.1, position - digits)
+            }
+        }
+    }
+
+    fn is_bytewise(&self) -> bool {
+        self.left.is_bytewise() && self.right.is_bytewise()
+    }
+}
+
+/// Sorting slices in place by discriminated keys, like `slice::sort_by_key`.
+pub trait DiscSliceExt<T> {
+    /// Sorts the slice by the keys that `f` extracts, in the order `desc`
+    /// discriminates them, keeping elements with equal keys in their original
+    /// order.
+    ///
+    /// Besides the buffers of the discrimination itself, this holds every
+    /// key alongside its element's index, and then the order those indices
+    /// come out in, so it takes a key and two indices per element of extra
+    /// memory.
+    fn disc_sort_by_key<'a, K: 'a, D, F>(&mut self, desc: &'a D, f: F)
+        where D: ?Sized + Discriminator<'a, K>,
+              F: FnMut(&T) -> K;
+
+    /// Sorts the slice by the keys that `f` extracts, in the order `desc`
+    /// discriminates them, without keeping elements with equal keys in order.
+    ///
+    /// Elements are swapped into place one digit of their keys at a time, as
+    /// in an American flag sort, so this takes no memory proportional to the
+    /// length of the slice.  `f` is called several times per element.
+    /// Subslices much shorter than the radix of their next digit are sorted
+    /// by comparing their keys instead.
+    ///
+    /// Longer slices with bytewise keys of up to 16 digits, such as plain
+    /// integers, are instead sorted by radix from their least significant
+    /// digit up, moving the elements themselves through a buffer as long as
+    /// the slice.
+    ///
+    /// Panics if `desc` can't break a key down into digits, as `Natural`
+    /// can't with keys that aren't below its limit.
+    fn disc_sort_unstable_by_key<K, D, F>(&mut self, desc: &D, f: F)
+        where D: ?Sized + Radix<K>,
+              F: FnMut(&T) -> K;
+}
+
+impl<T> DiscSliceExt<T> for [T] {
+    fn disc_sort_by_key<'a, K: 'a, D, F>(&mut self, desc: &'a D, mut f: F)
+        where D: ?Sized + Discriminator<'a, K>,
+              F: FnMut(&T) -> K
+    {
+        if self.len() <= 1 {
+            return;
+        }
+
+        let keys: Vec<(K, usize)> = self.iter().map(&mut f).zip(0..self.len()).collect();
+        permute(self, desc.discriminate_sorted(keys).flatten().collect());
+    }
+
+    fn disc_sort_unstable_by_key<K, D, F>(&mut self, desc: &D, mut f: F)
+        where D: ?Sized + Radix<K>,
+              F: FnMut(&T) -> K
+    {
+        if self.len() > SMALL && desc.is_bytewise() && desc.digits() <= 16 {
+            return radix_sort(self, desc.digits(), |item, p| desc.digit(&f(item), p) as u8);
+        }
+        flag_sort(desc, &mut f, self, 0);
+    }
+}
+
+// moves the element at `order[i]` to `i`, following each cycle of the
+// permutation and marking positions done as they're filled
+fn permute<T>(items: &mut [T], mut order: Vec<usize>) {
+    for start in 0..order.len() {
+        let mut i = start;
+        loop {
+            let j = mem::replace(&mut order[i], i);
+            if j == start {
+                break;
+            }
+            items.swap(i, j);
+            i = j;
+        }
+    }
+}
+
+// slices this short are compared digit by digit instead, since counting them
+// into buckets would cost more than sorting them
+const SMALL: usize = 32;
+
+// nor are slices counted into more than this many buckets per element, since
+// clearing and sweeping the buckets of every subslice would cost far more than
+// sorting it
+const SPARSE: usize = 4;
+
+fn compare<K, D: ?Sized + Radix<K>>(desc: &D, a: &K, b: &K, from: usize) -> Ordering {
+    (from..desc.digits()).map(|p| desc.digit(a, p).cmp(&desc.digit(b, p)))
+                         .find(|o| o.is_ne())
+                         .unwrap_or(Ordering::Equal)
+}
+
+fn flag_sort<T, K, D, F>(desc: &D, f: &mut F, items: &mut [T], position: usize)
+    where D: ?Sized + Radix<K>,
+          F: FnMut(&T) -> K
+{
+    if items.len() <= 1 || position >= desc.digits() {
+        return;
+    }
+    if items.len() <= SMALL || desc.radix(position) / SPARSE > items.len() {
+        items.sort_unstable_by(|a, b| compare(desc, &f(a), &f(b), position));
+        return;
+    }
+
+    // count digits, then turn the counts into the end of each bucket
+    let mut ends = vec![0; desc.radix(position)];
+    for item in items.iter() {
+        ends[desc.digit(&f(item), position)] += 1;
+    }
+    let mut total = 0;
+    for end in &mut ends {
+        total += *end;
+        *end = total;
+    }
+
+    // swap every element into the next free place in its bucket
+    let mut next: Vec<usize> = Some(0).into_iter()
+                                      .chain(ends[..ends.len() - 1].iter().cloned())
+                                      .collect();
+    for bucket in 0..ends.len() {
+        while next[bucket] < ends[bucket] {
+            let digit = desc.digit(&f(&items[next[bucket]]), position);
+            if digit == bucket {
+                next[bucket] += 1;
+            } else {
+                items.swap(next[bucket], next[digit]);
+                next[digit] += 1;
+            }
+        }
+    }
+
+    let mut start = 0;
+    for &end in &ends {
+        flag_sort(desc, f, &mut items[start..end], position + 1);
+        start = end;
+    }
+}
@@ -1,12 +1,15 @@
+use alloc::collections::VecDeque;
+use alloc::rc::Rc;
+#[cfg(feature = "parking_lot")]
+use alloc::sync::Arc;
+use core::cell::RefCell;
 use either::Either::{self, Left, Right};
-use std::cell::RefCell;
-use std::collections::VecDeque;
-use std::rc::Rc;
-use std::sync::Arc;
+#[cfg(feature = "parking_lot")]
 pub use parking_lot::RwLock;
 
 pub enum NonAtomic {}
 
+#[cfg(feature = "parking_lot")]
 pub enum Atomic {}
 
 pub trait Sharing<T: ?Sized> {
@@ -38,6 +41,7 @@ impl<T: ?Sized> Sharing<T> for NonAtomic {
     }
 }
 
+#[cfg(feature = "parking_lot")]
 impl<T: ?Sized> Sharing<T> for Atomic {
     type Shared = Arc<RwLock<T>>;
 
@@ -76,11 +80,12 @@ pub struct SplitEitherRight<A, B, I: ?Sized, S: ?Sized = NonAtomic>(
     <S as Sharing<SplitEitherImpl<A, B, I>>>::Shared)
     where S: Sharing<SplitEitherImpl<A, B, I>>;
 
-pub fn split_either<A, B, I, S: ?Sized>
+#[allow(clippy::type_complexity)]
+pub fn split_either<A, B, I, S>
     (inner: I)
      -> (SplitEitherLeft<A, B, I::IntoIter, S>, SplitEitherRight<A, B, I::IntoIter, S>)
     where I: IntoIterator<Item = Either<A, B>>,
-          S: Sharing<SplitEitherImpl<A, B, I::IntoIter>>
+          S: ?Sized + Sharing<SplitEitherImpl<A, B, I::IntoIter>>
 {
     let shared_left = <S as Sharing<SplitEitherImpl<A, B, I::IntoIter>>>::create(SplitEitherImpl {
         left: VecDeque::new(),
@@ -104,7 +109,7 @@ impl<A, B, I: ?Sized, S: ?Sized> Iterator for SplitEitherLeft<A, B, I, S>
             if let Some(val) = this.left.pop_front() {
                 return Some(val);
             }
-            while let Some(val_or_other) = this.inner.next() {
+            for val_or_other in &mut this.inner {
                 match val_or_other {
                     Left(val) => return Some(val),
                     Right(other) => this.right.push_back(other),
@@ -138,7 +143,7 @@ impl<A, B, I: ?Sized, S: ?Sized> DoubleEndedIterator for SplitEitherLeft<A, B, I
             if let Some(val) = this.left_back.pop_front() {
                 return Some(val);
             }
-            while let Some(val_or_other) = this.inner.next() {
+            for val_or_other in &mut this.inner {
                 match val_or_other {
                     Left(val) => return Some(val),
                     Right(other) => this.right_back.push_back(other),
@@ -160,7 +165,7 @@ impl<A, B, I: ?Sized, S: ?Sized> Iterator for SplitEitherRight<A, B, I, S>
             if let Some(val) = this.right.pop_front() {
                 return Some(val);
             }
-            while let Some(val_or_other) = this.inner.next() {
+            for val_or_other in &mut this.inner {
                 match val_or_other {
                     Right(val) => return Some(val),
                     Left(other) => this.left.push_back(other),
@@ -194,7 +199,7 @@ impl<A, B, I: ?Sized, S: ?Sized> DoubleEndedIterator for SplitEitherRight<A, B,
             if let Some(val) = this.right_back.pop_front() {
                 return Some(val);
             }
-            while let Some(val_or_other) = this.inner.next() {
+            for val_or_other in &mut this.inner {
                 match val_or_other {
                     Right(val) => return Some(val),
                     Left(other) => this.left_back.push_back(other),
@@ -0,0 +1,176 @@
+use core::iter;
+use core::str;
+use discriminator::{Discriminator, Map, Prefix, Product, Sum, Trivial, U64, U8, Usize};
+use either::Either::{self, Left, Right};
+#[cfg(feature = "semver")]
+use semver::Version;
+
+type Core = ((u64, u64), u64);
+
+// numeric identifiers, of any length, then alphanumeric ones
+type Identifier<'s> = Either<&'s str, &'s str>;
+
+type Identifiers<'s> = iter::Map<str::Split<'s, char>, fn(&'s str) -> Identifier<'s>>;
+
+// pre-release versions (`Left`) come before the release itself (`Right`)
+type Key<'s> = Either<(Core, Either<Identifiers<'s>, ()>), &'s str>;
+
+type Bytes = Map<for<'s> fn(&'s str) -> str::Bytes<'s>, Prefix<U8>>;
+
+// without leading zeros, a longer number is a larger one
+type Numeric = Map<for<'s> fn(&'s str) -> (usize, str::Bytes<'s>), Product<Usize, Prefix<U8>>>;
+
+type Inner = Sum<Product<Product<Product<U64, U64>, U64>,
+                        Sum<Prefix<Sum<Numeric, Bytes>>, Trivial>>,
+                Bytes>;
+
+const BYTES: Bytes = Map(str::bytes,
+                         Prefix { depth: usize::MAX,
+                                  element: U8, });
+
+const NUMERIC: Numeric = Map(digits,
+                             Product { is_right_biased: false,
+                                       left: Usize,
+                                       right: Prefix { depth: usize::MAX,
+                                                       element: U8, }, });
+
+const DESC: &Map<for<'s> fn(&'s str) -> Key<'s>, Inner> = &Map(key, INNER);
+
+const INNER: Inner =
+    Sum { is_right_biased: false,
+           left: Product { is_right_biased: false,
+                           left: Product { is_right_biased: false,
+                                           left: Product { is_right_biased: false,
+                                                           left: U64,
+                                                           right: U64, },
+                                           right: U64, },
+                           right: Sum { is_right_biased: false,
+                                        left: Prefix { depth: usize::MAX,
+                                                       element: Sum { is_right_biased: false,
+                                                                      left: NUMERIC,
+                                                                      right: BYTES, }, },
+                                        right: Trivial, }, },
+           right: BYTES, };
+
+fn numeric(s: &str) -> Option<u64> {
+    if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) || (s.len() > 1 && s.starts_with('0')) {
+        None
+    } else {
+        s.parse().ok()
+    }
+}
+
+fn digits(s: &str) -> (usize, str::Bytes<'_>) {
+    (s.len(), s.bytes())
+}
+
+fn identifier(s: &str) -> Identifier<'_> {
+    if s.bytes().all(|b| b.is_ascii_digit()) {
+        Left(s)
+    } else {
+        Right(s)
+    }
+}
+
+fn identifiers(pre: &str) -> Identifiers<'_> {
+    pre.split('.').map(identifier as fn(&str) -> Identifier<'_>)
+}
+
+fn is_valid_build(s: &str) -> bool {
+    !s.is_empty() && s.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-')
+}
+
+fn is_valid_identifier(s: &str) -> bool {
+    is_valid_build(s) &&
+    !(s.len() > 1 && s.starts_with('0') && s.bytes().all(|b| b.is_ascii_digit()))
+}
+
+fn parse(s: &str) -> Option<(Core, Option<&str>)> {
+    let s = match s.split_once('+') {
+        Some((s, build)) if build.split('.').all(is_valid_build) => s,
+        Some(_) => return None,
+        None => s,
+    };
+    let (core, pre) = match s.find('-') {
+        Some(i) => (&s[..i], Some(&s[i + 1..])),
+        None => (s, None),
+    };
+
+    let mut parts = core.split('.');
+    let major = numeric(parts.next()?)?;
+    let minor = numeric(parts.next()?)?;
+    let patch = numeric(parts.next()?)?;
+    if parts.next().is_some() {
+        return None;
+    }
+
+    if pre.is_some_and(|pre| !pre.split('.').all(is_valid_identifier)) {
+        return None;
+    }
+    Some((((major, minor), patch), pre))
+}
+
+fn key(s: &str) -> Key<'_> {
+    match parse(s) {
+        Some((core, Some(pre))) => Left((core, Left(identifiers(pre)))),
+        Some((core, None)) => Left((core, Right(()))),
+        None => Right(s),
+    }
+}
+
+/// Discriminates [semantic versions](https://semver.org) by precedence.
+///
+/// Versions are ordered by major, minor and patch number, then by pre-release
+/// identifiers (numeric ones compared numerically, however long, and before
+/// alphanumeric ones, which are compared bytewise), with a release coming
+/// after all of its pre-releases, as with `semver::Version::cmp_precedence`.
+/// Build metadata must be well formed but is otherwise ignored, so `1.0.0+a`
+/// and `1.0.0+b` fall into the same group.
+///
+/// Strings that are not valid versions come after every valid one, grouped
+/// and ordered by their raw bytes.  As with the `semver` crate, that includes
+/// versions whose major, minor or patch number is larger than `u64::MAX`.
+#[derive(Debug,Copy,Clone,Default)]
+pub struct SemVer;
+
+impl SemVer {
+    pub fn new() -> Self {
+        SemVer
+    }
+}
+
+impl<'a, 's: 'a> Discriminator<'a, &'s str> for SemVer {
+    type Groups<V: 'a> = <Inner as Discriminator<'a, Key<'s>>>::Groups<V>;
+
+    fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> Self::Groups<V>
+        where I: IntoIterator,
+              I::Item: Into<(&'s str, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        DESC.discriminate_sorted(pairs)
+    }
+}
+
+#[cfg(feature = "semver")]
+fn version_key(v: &Version) -> Key<'_> {
+    let core = ((v.major, v.minor), v.patch);
+    if v.pre.is_empty() {
+        Left((core, Right(())))
+    } else {
+        Left((core, Left(identifiers(v.pre.as_str()))))
+    }
+}
+
+#[cfg(feature = "semver")]
+impl<'a, 's: 'a> Discriminator<'a, &'s Version> for SemVer {
+    type Groups<V: 'a> = <Inner as Discriminator<'a, Key<'s>>>::Groups<V>;
+
+    fn discriminate_sorted<V: 'a, I>(&'a self, pairs: I) -> Self::Groups<V>
+        where I: IntoIterator,
+              I::Item: Into<(&'s Version, V)>,
+              I::IntoIter: DoubleEndedIterator + 'a
+    {
+        const DESC: &Map<for<'s> fn(&'s Version) -> Key<'s>, Inner> = &Map(version_key, INNER);
+        DESC.discriminate_sorted(pairs)
+    }
+}